@@ -0,0 +1,355 @@
+//! Local sqlite archive of every comment we've seen, so old ones can be looked up later.
+
+use std::path::Path;
+
+use rusqlite::{params, Connection};
+
+use crate::YTComment;
+
+/// A comment archive on disk.
+pub struct Archive {
+    connection: Connection,
+}
+
+/// Something went wrong with the archive database.
+#[derive(Debug)]
+pub enum ArchiveFail {
+    Sqlite(rusqlite::Error),
+}
+
+impl std::fmt::Display for ArchiveFail {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Sqlite(error) => write!(f, "Comment archive failure! : {error}"),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for ArchiveFail {
+    fn from(error: rusqlite::Error) -> Self {
+        Self::Sqlite(error)
+    }
+}
+
+impl Archive {
+    /// Opens the archive, creating it if it doesn't exist yet.
+    ///
+    /// # Errors
+    ///
+    /// The database couldn't be opened or set up.
+    pub fn open(path: &Path) -> Result<Self, ArchiveFail> {
+        Self::set_up(Connection::open(path)?)
+    }
+
+    fn set_up(connection: Connection) -> Result<Self, ArchiveFail> {
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS videos (
+                video_id TEXT PRIMARY KEY,
+                title TEXT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS comments (
+                comment_id TEXT PRIMARY KEY,
+                video_id TEXT NOT NULL,
+                parent_id TEXT,
+                author_name TEXT NOT NULL,
+                author_channel_id TEXT,
+                content TEXT NOT NULL,
+                like_count INTEGER NOT NULL,
+                published INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS comments_by_video ON comments (video_id, published);
+            CREATE INDEX IF NOT EXISTS comments_by_time ON comments (published);",
+        )?;
+
+        Ok(Self { connection })
+    }
+
+    /// Saves a batch of comments from one video, all or nothing. Comments that are already
+    /// archived get refreshed.
+    ///
+    /// # Errors
+    ///
+    /// The database couldn't be written to, in which case none of them were saved.
+    pub fn store(
+        &mut self,
+        video_id: &str,
+        video_title: &str,
+        comments: &[YTComment],
+    ) -> Result<(), ArchiveFail> {
+        let transaction = self.connection.transaction()?;
+
+        // Titles can change, keep the newest one.
+        transaction.execute(
+            "INSERT INTO videos (video_id, title) VALUES (?1, ?2)
+             ON CONFLICT (video_id) DO UPDATE SET title = excluded.title",
+            params![video_id, video_title],
+        )?;
+
+        for comment in comments {
+            // Replies point back at the thread they're in.
+            let parent_id: Option<&str> = comment
+                .reply_to
+                .as_ref()
+                .map(|_| comment.thread_id.as_str());
+
+            // SQLite only does signed integers.
+            let like_count: i64 = i64::try_from(comment.like_count).unwrap_or(i64::MAX);
+            let published: i64 = i64::try_from(comment.timestamp).unwrap_or(i64::MAX);
+
+            // Seeing a comment again just refreshes it (edits, likes).
+            transaction.execute(
+                "INSERT OR REPLACE INTO comments (
+                    comment_id, video_id, parent_id, author_name,
+                    author_channel_id, content, like_count, published
+                ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                params![
+                    comment.id,
+                    video_id,
+                    parent_id,
+                    comment.author_name,
+                    comment.author_channel_id,
+                    comment.content,
+                    like_count,
+                    published,
+                ],
+            )?;
+        }
+
+        transaction.commit()?;
+        Ok(())
+    }
+}
+
+/// What to look for in the archive. Every filter is optional, and they all have to match.
+#[derive(Debug, Default)]
+pub struct SearchQuery {
+    /// Case insensitive substring of the comment.
+    pub text: Option<String>,
+    /// Case insensitive substring of the author's name.
+    pub author: Option<String>,
+    pub video_id: Option<String>,
+    /// Earliest timestamp, inclusive.
+    pub since: Option<u64>,
+    /// Latest timestamp, exclusive.
+    pub until: Option<u64>,
+}
+
+/// A comment pulled back out of the archive.
+#[derive(Debug)]
+pub struct ArchivedComment {
+    pub video_id: String,
+    pub video_title: String,
+    pub comment: YTComment,
+}
+
+impl Archive {
+    /// Finds matching comments, oldest first.
+    ///
+    /// # Errors
+    ///
+    /// The database couldn't be read.
+    pub fn search(&self, query: &SearchQuery) -> Result<Vec<ArchivedComment>, ArchiveFail> {
+        let mut statement = self.connection.prepare(
+            "SELECT c.comment_id, c.video_id, COALESCE(v.title, ''), c.parent_id,
+                    p.author_name, c.author_name, c.author_channel_id, c.content,
+                    c.like_count, c.published
+             FROM comments c
+             LEFT JOIN videos v ON v.video_id = c.video_id
+             LEFT JOIN comments p ON p.comment_id = c.parent_id
+             WHERE (?1 IS NULL OR instr(lower(c.content), lower(?1)) > 0)
+               AND (?2 IS NULL OR instr(lower(c.author_name), lower(?2)) > 0)
+               AND (?3 IS NULL OR c.video_id = ?3)
+               AND (?4 IS NULL OR c.published >= ?4)
+               AND (?5 IS NULL OR c.published < ?5)
+             ORDER BY c.published, c.comment_id",
+        )?;
+
+        // SQLite only does signed integers.
+        let since: Option<i64> = query
+            .since
+            .map(|time| i64::try_from(time).unwrap_or(i64::MAX));
+        let until: Option<i64> = query
+            .until
+            .map(|time| i64::try_from(time).unwrap_or(i64::MAX));
+
+        let rows = statement.query_map(
+            params![query.text, query.author, query.video_id, since, until],
+            |row| {
+                let comment_id: String = row.get(0)?;
+                let parent_id: Option<String> = row.get(3)?;
+                let parent_author: Option<String> = row.get(4)?;
+                let like_count: i64 = row.get(8)?;
+                let published: i64 = row.get(9)?;
+                Ok(ArchivedComment {
+                    video_id: row.get(1)?,
+                    video_title: row.get(2)?,
+                    comment: YTComment {
+                        // Replies whose parent never made it into the archive have no name to show.
+                        reply_to: parent_id
+                            .as_ref()
+                            .map(|_| parent_author.unwrap_or_else(|| "a comment".to_string())),
+                        thread_id: parent_id.unwrap_or_else(|| comment_id.clone()),
+                        id: comment_id,
+                        author_name: row.get(5)?,
+                        author_channel_id: row.get(6)?,
+                        content: row.get(7)?,
+                        like_count: u64::try_from(like_count).unwrap_or_default(),
+                        timestamp: u64::try_from(published).unwrap_or_default(),
+                    },
+                })
+            },
+        )?;
+
+        let mut results: Vec<ArchivedComment> = Vec::new();
+        for row in rows {
+            results.push(row?);
+        }
+        Ok(results)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn comment(id: &str, author: &str, content: &str, timestamp: u64) -> YTComment {
+        YTComment {
+            id: id.to_string(),
+            content: content.to_string(),
+            author_name: author.to_string(),
+            author_channel_id: None,
+            like_count: 0,
+            timestamp,
+            thread_id: id.to_string(),
+            reply_to: None,
+        }
+    }
+
+    fn reply(id: &str, thread: &YTComment, author: &str, timestamp: u64) -> YTComment {
+        YTComment {
+            thread_id: thread.id.clone(),
+            reply_to: Some(thread.author_name.clone()),
+            ..comment(id, author, "agreed", timestamp)
+        }
+    }
+
+    fn ids(archive: &Archive, query: &SearchQuery) -> Vec<String> {
+        archive
+            .search(query)
+            .unwrap()
+            .into_iter()
+            .map(|result| result.comment.id)
+            .collect()
+    }
+
+    fn example() -> Archive {
+        let mut archive = Archive::set_up(Connection::open_in_memory().unwrap()).unwrap();
+        let first = comment("a", "Alice", "First!", 100);
+        let answer = reply("b", &first, "Bob", 200);
+        archive
+            .store("vid1", "Old title", &[first, answer])
+            .unwrap();
+        archive
+            .store(
+                "vid2",
+                "Other video",
+                &[comment("c", "Carol", "Nice video", 300)],
+            )
+            .unwrap();
+        archive
+    }
+
+    #[test]
+    fn comments_come_back_out() {
+        let mut archive = example();
+        // Seeing a comment again updates it rather than adding another.
+        let edited = YTComment {
+            like_count: 5,
+            ..comment("a", "Alice", "First! (edit: wow)", 100)
+        };
+        archive.store("vid1", "New title", &[edited]).unwrap();
+
+        let results = archive.search(&SearchQuery::default()).unwrap();
+        assert_eq!(results.len(), 3);
+        let first = &results[0];
+        assert_eq!(first.video_id, "vid1");
+        assert_eq!(first.video_title, "New title");
+        assert_eq!(first.comment.content, "First! (edit: wow)");
+        assert_eq!(first.comment.like_count, 5);
+        assert_eq!(first.comment.reply_to, None);
+
+        // Replies find their way back to who they were replying to.
+        let answer = &results[1];
+        assert_eq!(answer.comment.thread_id, "a");
+        assert_eq!(answer.comment.reply_to.as_deref(), Some("Alice"));
+    }
+
+    #[test]
+    fn replies_to_missing_comments_still_show() {
+        let mut archive = example();
+        let thread = comment("gone", "Nobody", "", 0);
+        archive
+            .store("vid2", "Other video", &[reply("d", &thread, "Dave", 400)])
+            .unwrap();
+        let results = archive
+            .search(&SearchQuery {
+                author: Some("dave".to_string()),
+                ..SearchQuery::default()
+            })
+            .unwrap();
+        assert_eq!(results[0].comment.reply_to.as_deref(), Some("a comment"));
+    }
+
+    #[test]
+    fn every_filter_narrows_it_down() {
+        let archive = example();
+        let cases = [
+            (
+                SearchQuery {
+                    text: Some("NICE".to_string()),
+                    ..SearchQuery::default()
+                },
+                vec!["c"],
+            ),
+            (
+                SearchQuery {
+                    author: Some("ali".to_string()),
+                    ..SearchQuery::default()
+                },
+                vec!["a"],
+            ),
+            (
+                SearchQuery {
+                    video_id: Some("vid1".to_string()),
+                    ..SearchQuery::default()
+                },
+                vec!["a", "b"],
+            ),
+            (
+                SearchQuery {
+                    since: Some(200),
+                    ..SearchQuery::default()
+                },
+                vec!["b", "c"],
+            ),
+            (
+                SearchQuery {
+                    until: Some(200),
+                    ..SearchQuery::default()
+                },
+                vec!["a"],
+            ),
+            (
+                SearchQuery {
+                    video_id: Some("vid1".to_string()),
+                    since: Some(150),
+                    ..SearchQuery::default()
+                },
+                vec!["b"],
+            ),
+        ];
+        for (query, expected) in cases {
+            assert_eq!(ids(&archive, &query), expected, "{query:?}");
+        }
+    }
+}
@@ -0,0 +1,206 @@
+// Making sense of the different ways people point at a channel, or a video or playlist.
+
+/// A channel, the way it was given to us. Only [`ChannelRef::Id`] can go straight into a
+/// request, the rest get looked up by [`test_channel_and_key`](crate::test_channel_and_key).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChannelRef {
+    /// `UC...`, or a `/channel/UC...` URL.
+    Id(String),
+    /// `@name`, or a `youtube.com/@name` URL. Without the `@`.
+    Handle(String),
+    /// A `/user/name` URL, from before handles.
+    Username(String),
+    /// A `/c/name` URL. There's no lookup for these, so they get tried as a handle and then
+    /// as a username.
+    Custom(String),
+    /// A `watch?v=`, `youtu.be/` or `/shorts/` link to a video, which stands for whoever
+    /// uploaded it. The ID of the video.
+    Video(String),
+}
+
+/// A channel that turned out to exist.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChannelInfo {
+    /// The `UC...` ID, whatever it was looked up by.
+    pub id: String,
+    pub name: String,
+}
+
+impl ChannelRef {
+    /// Reads a channel ID, `@handle`, channel URL, or video link. `None` if it's a URL that
+    /// isn't any of those.
+    #[must_use]
+    pub fn parse(input: &str) -> Option<Self> {
+        // Anything past the path doesn't matter, other than which video a watch link is for.
+        let input: &str = input.trim().split('#').next().unwrap_or_default();
+        let (input, query): (&str, &str) = input.split_once('?').unwrap_or((input, ""));
+        let without_scheme: &str = input
+            .strip_prefix("https://")
+            .or_else(|| input.strip_prefix("http://"))
+            .unwrap_or(input);
+        if let Some(id) = without_scheme.strip_prefix("youtu.be/") {
+            let id: &str = id.trim_end_matches('/');
+            return (!id.is_empty()).then(|| Self::Video(id.to_string()));
+        }
+        let path: Option<&str> = ["www.youtube.com/", "m.youtube.com/", "youtube.com/"]
+            .iter()
+            .find_map(|host| without_scheme.strip_prefix(host));
+        let is_url: bool = path.is_some();
+
+        let mut parts = path
+            .unwrap_or(without_scheme)
+            .split('/')
+            .filter(|part| !part.is_empty());
+        match (parts.next()?, parts.next()) {
+            // Handle URLs can have a tab on the end, like @name/videos.
+            (handle, _) if handle.len() > 1 && handle.starts_with('@') => {
+                Some(Self::Handle(handle[1..].to_string()))
+            }
+            ("channel", Some(id)) if is_url => Some(Self::Id(id.to_string())),
+            ("c", Some(name)) if is_url => Some(Self::Custom(name.to_string())),
+            ("user", Some(name)) if is_url => Some(Self::Username(name.to_string())),
+            ("watch", None) if is_url => query
+                .split('&')
+                .find_map(|param| param.strip_prefix("v="))
+                .filter(|id| !id.is_empty())
+                .map(|id| Self::Video(id.to_string())),
+            ("shorts" | "live", Some(id)) if is_url => Some(Self::Video(id.to_string())),
+            // Not a URL at all, so it's hopefully a plain ID.
+            (id, None) if !is_url => Some(Self::Id(id.to_string())),
+            _ => None,
+        }
+    }
+
+    // The `channels` query parameters to try, in order. First one that finds a channel wins.
+    // Videos have to be looked up first, to find out whose they are.
+    pub(crate) fn lookups(&self) -> Vec<String> {
+        match self {
+            Self::Id(id) => vec![format!("id={}", encode(id))],
+            Self::Handle(handle) => vec![format!("forHandle={}", encode(handle))],
+            Self::Username(name) => vec![format!("forUsername={}", encode(name))],
+            // Most custom URLs turned into handles, the really old ones are usernames.
+            Self::Custom(name) => vec![
+                format!("forHandle={}", encode(name)),
+                format!("forUsername={}", encode(name)),
+            ],
+            Self::Video(_) => Vec::new(),
+        }
+    }
+}
+
+/// Pulls the ID out of a video link, like a `watch?v=` or `youtu.be/` one. Anything else is
+/// taken to be an ID already.
+#[must_use]
+pub fn video_id_of(input: &str) -> String {
+    match ChannelRef::parse(input) {
+        Some(ChannelRef::Video(id)) => id,
+        _ => input.trim().to_string(),
+    }
+}
+
+/// Pulls the ID out of a playlist link, or a video link with a playlist in it. Anything else
+/// is taken to be an ID already.
+#[must_use]
+pub fn playlist_id_of(input: &str) -> String {
+    let input: &str = input.trim();
+    let query: &str = input
+        .split('#')
+        .next()
+        .and_then(|link| link.split_once('?'))
+        .map_or("", |(_, query)| query);
+    query
+        .split('&')
+        .find_map(|param| param.strip_prefix("list="))
+        .filter(|id| !id.is_empty())
+        .unwrap_or(input)
+        .to_string()
+}
+
+pub fn encode(text: &str) -> String {
+    // Handles can have just about any letter in them, which can't go in a URL as is.
+    // Things copied out of a URL are already encoded, so % is left alone.
+    text.bytes()
+        .map(|byte| {
+            if byte.is_ascii_alphanumeric() || b"-._~%".contains(&byte) {
+                char::from(byte).to_string()
+            } else {
+                format!("%{byte:02X}")
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn channels_can_be_given_every_which_way() {
+        let cases = [
+            ("UC123", ChannelRef::Id("UC123".to_string())),
+            ("@someone", ChannelRef::Handle("someone".to_string())),
+            (
+                "https://www.youtube.com/@someone/videos?si=abc",
+                ChannelRef::Handle("someone".to_string()),
+            ),
+            (
+                "youtube.com/channel/UC123",
+                ChannelRef::Id("UC123".to_string()),
+            ),
+            (
+                "http://m.youtube.com/c/Someone",
+                ChannelRef::Custom("Someone".to_string()),
+            ),
+            (
+                "https://youtube.com/user/someone/",
+                ChannelRef::Username("someone".to_string()),
+            ),
+            (
+                "https://www.youtube.com/watch?feature=share&v=abc#t=10",
+                ChannelRef::Video("abc".to_string()),
+            ),
+            (
+                "https://youtu.be/abc?si=xyz",
+                ChannelRef::Video("abc".to_string()),
+            ),
+            (
+                "youtube.com/shorts/abc",
+                ChannelRef::Video("abc".to_string()),
+            ),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(ChannelRef::parse(input), Some(expected), "{input}");
+        }
+        assert_eq!(
+            ChannelRef::parse("https://www.youtube.com/playlist?list=abc"),
+            None
+        );
+        assert_eq!(ChannelRef::parse("https://www.youtube.com/watch"), None);
+        assert_eq!(ChannelRef::parse(""), None);
+    }
+
+    #[test]
+    fn video_and_playlist_links_are_cut_down_to_ids() {
+        assert_eq!(
+            video_id_of("https://www.youtube.com/watch?v=abc&t=5"),
+            "abc"
+        );
+        assert_eq!(video_id_of("youtu.be/abc"), "abc");
+        assert_eq!(video_id_of(" abc "), "abc");
+        assert_eq!(
+            playlist_id_of("https://www.youtube.com/playlist?list=PL123"),
+            "PL123"
+        );
+        assert_eq!(
+            playlist_id_of("https://www.youtube.com/watch?v=abc&list=PL123#t=5"),
+            "PL123"
+        );
+        assert_eq!(playlist_id_of("PL123"), "PL123");
+    }
+
+    #[test]
+    fn handles_are_encoded_for_the_lookup() {
+        let lookups = ChannelRef::Handle("café".to_string()).lookups();
+        assert_eq!(lookups, ["forHandle=caf%C3%A9"]);
+    }
+}
@@ -0,0 +1,440 @@
+// The config file, and squashing it together with the command line into the settings we
+// actually run with. Anything on the command line wins over the file.
+
+use std::path::{Path, PathBuf};
+
+use colored::Colorize;
+use serde::Deserialize;
+
+use crate::output::{CommentFilter, OutputFormat, Sink};
+use crate::Args;
+use youtube_comment_feed::{playlist_id_of, quota, redact, video_id_of};
+
+// Everything the config file can hold. Every bit of it is optional.
+//
+//     api_key_file = "/run/secrets/youtube"   # Or api_keys = ["KEY", "OTHER_KEY"]
+//     channel_id = "UC..."                   # Or channel_ids = ["UC...", "UC..."]
+//     videos = ["VIDEO_ID"]                   # And/or playlist = "PL..."
+//     format = "json"
+//
+//     [polling]
+//     min_delay = 30
+//
+//     [filters]
+//     exclude = ["(?i)buy now"]
+//
+//     [[notify]]
+//     webhook = "https://example.com/hook"
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    api_key: Option<String>,
+    api_keys: Vec<String>,
+    api_key_file: Option<PathBuf>,
+    channel_id: Option<String>,
+    channel_ids: Vec<String>,
+    videos: Vec<String>,
+    playlist: Option<String>,
+    state: Option<PathBuf>,
+    archive: Option<PathBuf>,
+    quota_budget: Option<u64>,
+    verbose: Option<bool>,
+    format: Option<OutputFormat>,
+    polling: Polling,
+    filters: Filters,
+    notify: Vec<Sink>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+struct Polling {
+    list_refresh: Option<u64>,
+    min_delay: Option<u16>,
+    max_delay: Option<u16>,
+    max_pages: Option<u16>,
+    parallel: Option<u16>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+struct Filters {
+    include: Vec<String>,
+    exclude: Vec<String>,
+    ignore_authors: Vec<String>,
+}
+
+// What we're actually running with, once the file and the command line are sorted out.
+pub struct Settings {
+    pub api_keys: Vec<String>, // Never empty.
+    pub channel_ids: Vec<String>,
+    pub video_ids: Vec<String>,
+    pub playlist_id: Option<String>, // At least one of these three has something in it.
+    pub list_refresh: u64,
+    pub min_delay: u16,
+    pub max_delay: u16,
+    pub max_pages: u16,
+    pub parallel: u16,
+    pub state: Option<PathBuf>,
+    pub archive: Option<PathBuf>,
+    pub quota_budget: u64,
+    pub verbose: bool,
+    pub format: OutputFormat,
+    pub filter: CommentFilter,
+    pub sinks: Vec<Sink>,
+}
+
+pub fn load(explicit: Option<&Path>) -> Result<Config, String> {
+    // Reads the config file. One asked for with --config has to be there, but the default
+    // one is only used if it exists.
+    let path: PathBuf = match (explicit, default_path()) {
+        (Some(path), _) => path.to_path_buf(),
+        (None, Some(path)) if path.exists() => path,
+        (None, _) => return Ok(Config::default()),
+    };
+    let text: String = match std::fs::read_to_string(&path) {
+        Ok(okay) => okay,
+        Err(e) => return Err(format!("Couldn't read {}! : {e}", path.display())),
+    };
+    parse(&text).map_err(|error| format!("Bad config file {}! : {error}", path.display()))
+}
+
+fn parse(text: &str) -> Result<Config, String> {
+    // Just where it went wrong, and which setting. toml's own errors quote the line and the
+    // value they choked on, which could be an API key, and those haven't been hidden yet.
+    toml::from_str(text).map_err(|error: toml::de::Error| {
+        let Some(span) = error.span() else {
+            return "couldn't make sense of it".to_string();
+        };
+        let before: &str = text.get(..span.start).unwrap_or_default();
+        let line_start: usize = before.rfind('\n').map_or(0, |newline| newline + 1);
+        let line: usize = before.matches('\n').count() + 1;
+        let column: usize = before.len() - line_start + 1;
+
+        // Setting names are all lowercase, keys never are, so a name is safe to show.
+        let setting: Option<&str> = text[line_start..]
+            .lines()
+            .next()
+            .and_then(|setting_line| setting_line.split_once('='))
+            .map(|(name, _)| name.trim())
+            .filter(|name| {
+                !name.is_empty()
+                    && name
+                        .bytes()
+                        .all(|byte| byte.is_ascii_lowercase() || byte == b'_' || byte == b'.')
+            });
+        let problem: String = setting.map_or_else(
+            || "couldn't make sense of it".to_string(),
+            |name| format!("problem with {name}"),
+        );
+        format!("line {line}, column {column} : {problem}")
+    })
+}
+
+fn default_path() -> Option<PathBuf> {
+    // $XDG_CONFIG_HOME/youtube_comment_feed/config.toml, which is usually under ~/.config.
+    let config_home: PathBuf = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(config_home.join("youtube_comment_feed").join("config.toml"))
+}
+
+// Pick the command line's value if there is one, then the file's, then the default.
+fn pick<T>(flag: Option<T>, file: Option<T>, default: T) -> T {
+    flag.or(file).unwrap_or(default)
+}
+
+// Lists from the command line replace the file's entirely, they don't add to them.
+fn pick_list(flag: &[String], file: Vec<String>) -> Vec<String> {
+    if flag.is_empty() {
+        file
+    } else {
+        flag.to_vec()
+    }
+}
+
+// Where the keys come from, first place that has any wins.
+// Flags, then the environment, then the config file, then the keyring.
+// More than one key means they get rotated through, see KeyRotation.
+fn find_api_keys(args: &Args, config: &Config) -> Result<Vec<String>, String> {
+    if !args.api_key.is_empty() {
+        eprintln!(
+            "{}",
+            "--api-key shows up in shell history and ps, consider $YOUTUBE_API_KEY or --api-key-file instead."
+                .yellow()
+        );
+        return Ok(args.api_key.clone());
+    }
+    if let Some(path) = &args.api_key_file {
+        return read_key_file(path);
+    }
+    // Comma separated, for more than one.
+    let from_env: Vec<String> = std::env::var("YOUTUBE_API_KEY")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|key| !key.is_empty())
+        .map(str::to_string)
+        .collect();
+    if !from_env.is_empty() {
+        return Ok(from_env);
+    }
+    if let Some(path) = &config.api_key_file {
+        return read_key_file(path);
+    }
+    let from_config: Vec<String> = config
+        .api_key
+        .iter()
+        .chain(&config.api_keys)
+        .cloned()
+        .collect();
+    if !from_config.is_empty() {
+        return Ok(from_config);
+    }
+    if let Some(key) = keyring_key()? {
+        return Ok(vec![key]);
+    }
+    Err("No API key! Set $YOUTUBE_API_KEY, pass --api-key-file, or set api_key_file in the config file.".to_string())
+}
+
+fn read_key_file(path: &Path) -> Result<Vec<String>, String> {
+    // One key per line. Blank lines and # comments are skipped.
+    let text: String = match std::fs::read_to_string(path) {
+        Ok(okay) => okay,
+        Err(e) => {
+            return Err(format!(
+                "Couldn't read the API key from {}! : {e}",
+                path.display()
+            ))
+        }
+    };
+    let keys: Vec<String> = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect();
+    if keys.is_empty() {
+        return Err(format!("{} is empty, no API key in there!", path.display()));
+    }
+    Ok(keys)
+}
+
+#[cfg(feature = "keyring")]
+fn keyring_key() -> Result<Option<String>, String> {
+    // Not being in the keyring is fine, the keyring being broken isn't.
+    let entry = match keyring::Entry::new("youtube_comment_feed", "api_key") {
+        Ok(okay) => okay,
+        Err(e) => return Err(format!("Couldn't open the keyring! : {e}")),
+    };
+    match entry.get_password() {
+        Ok(key) => Ok(Some(key)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(format!("Couldn't read the API key from the keyring! : {e}")),
+    }
+}
+
+#[cfg(not(feature = "keyring"))]
+#[allow(clippy::unnecessary_wraps)] // Matches the keyring version.
+const fn keyring_key() -> Result<Option<String>, String> {
+    Ok(None)
+}
+
+pub fn resolve(args: &Args, config: Config) -> Result<Settings, String> {
+    let api_keys: Vec<String> = find_api_keys(args, &config)?;
+    // Never print them, whatever goes wrong.
+    for key in &api_keys {
+        redact::hide(key);
+    }
+    // What to watch. Asking for anything on the command line replaces all of the file's,
+    // so --video doesn't also watch the file's channel.
+    let (channel_ids, video_ids, playlist_id): (Vec<String>, Vec<String>, Option<String>) =
+        if args.channel_id.is_empty() && args.video.is_empty() && args.playlist.is_none() {
+            (
+                config
+                    .channel_id
+                    .into_iter()
+                    .chain(config.channel_ids)
+                    .collect(),
+                config.videos,
+                config.playlist,
+            )
+        } else {
+            (
+                args.channel_id.clone(),
+                args.video.clone(),
+                args.playlist.clone(),
+            )
+        };
+    // Links pasted in from a browser work as well as IDs.
+    let video_ids: Vec<String> = video_ids.iter().map(|video| video_id_of(video)).collect();
+    let playlist_id: Option<String> = playlist_id.as_deref().map(playlist_id_of);
+    if channel_ids.is_empty() && video_ids.is_empty() && playlist_id.is_none() {
+        return Err(
+            "Nothing to watch! Pass --channel-id, --video or --playlist, or set channel_id in the config file."
+                .to_string(),
+        );
+    }
+
+    let parallel: u16 = pick(args.parallel, config.polling.parallel, 4);
+    if parallel == 0 {
+        return Err("parallel has to be at least 1!".to_string());
+    }
+
+    let filter: CommentFilter = CommentFilter::build(
+        &pick_list(&args.include, config.filters.include),
+        &pick_list(&args.exclude, config.filters.exclude),
+        &pick_list(&args.ignore_author, config.filters.ignore_authors),
+    )?;
+
+    // Sinks on the command line replace the file's, same as the filters.
+    let mut sinks: Vec<Sink> = Vec::new();
+    sinks.extend(args.notify_command.iter().cloned().map(Sink::Command));
+    sinks.extend(args.webhook.iter().cloned().map(Sink::Webhook));
+    if sinks.is_empty() {
+        sinks = config.notify;
+    }
+
+    Ok(Settings {
+        api_keys,
+        channel_ids,
+        video_ids,
+        playlist_id,
+        list_refresh: pick(args.list_refresh, config.polling.list_refresh, 600),
+        min_delay: pick(args.min_delay, config.polling.min_delay, 10),
+        max_delay: pick(args.max_delay, config.polling.max_delay, 3600),
+        max_pages: pick(args.max_pages, config.polling.max_pages, 10),
+        parallel,
+        state: args.state.clone().or(config.state),
+        archive: args.archive.clone().or(config.archive),
+        quota_budget: pick(
+            args.quota_budget,
+            config.quota_budget,
+            quota::DEFAULT_BUDGET,
+        ),
+        verbose: args.verbose || config.verbose.unwrap_or_default(),
+        format: pick(args.format, config.format, OutputFormat::Text),
+        filter,
+        sinks,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    const EXAMPLE: &str = r#"
+        api_key = "FILE_KEY"
+        channel_id = "UC123"
+        format = "json"
+
+        [polling]
+        min_delay = 30
+        parallel = 2
+
+        [filters]
+        exclude = ["(?i)buy now"]
+        ignore_authors = ["spammer"]
+
+        [[notify]]
+        command = "cat"
+
+        [[notify]]
+        webhook = "https://example.com/hook"
+    "#;
+
+    #[test]
+    fn config_files_fill_in_the_blanks() {
+        let args = Args::parse_from(["feed"]);
+        let settings = resolve(&args, parse(EXAMPLE).unwrap()).unwrap();
+        assert_eq!(settings.api_keys, ["FILE_KEY"]);
+        assert_eq!(settings.channel_ids, ["UC123"]);
+        assert_eq!(settings.format, OutputFormat::Json);
+        assert_eq!(settings.min_delay, 30);
+        assert_eq!(settings.max_delay, 3600);
+        assert_eq!(settings.parallel, 2);
+        assert_eq!(settings.filter.exclude.len(), 1);
+        assert_eq!(settings.filter.ignore_authors, ["spammer"]);
+        assert_eq!(
+            settings.sinks,
+            [
+                Sink::Command("cat".to_string()),
+                Sink::Webhook("https://example.com/hook".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn flags_win_over_the_file() {
+        let args = Args::parse_from([
+            "feed",
+            "--api-key",
+            "FLAG_KEY",
+            "--min-delay",
+            "5",
+            "--format",
+            "text",
+            "--webhook",
+            "https://example.com/other",
+        ]);
+        let settings = resolve(&args, parse(EXAMPLE).unwrap()).unwrap();
+        assert_eq!(settings.api_keys, ["FLAG_KEY"]);
+        assert_eq!(settings.channel_ids, ["UC123"]);
+        assert_eq!(settings.min_delay, 5);
+        assert_eq!(settings.format, OutputFormat::Text);
+        assert_eq!(
+            settings.sinks,
+            [Sink::Webhook("https://example.com/other".to_string())]
+        );
+
+        // Picking videos means not watching the file's channel.
+        let args = Args::parse_from(["feed", "--video", "abc", "--video", "youtu.be/def"]);
+        let settings = resolve(&args, parse(EXAMPLE).unwrap()).unwrap();
+        assert!(settings.channel_ids.is_empty());
+        assert_eq!(settings.video_ids, ["abc", "def"]);
+
+        let args = Args::parse_from([
+            "feed",
+            "--playlist",
+            "https://www.youtube.com/playlist?list=PL123",
+        ]);
+        let settings = resolve(&args, parse(EXAMPLE).unwrap()).unwrap();
+        assert_eq!(settings.playlist_id.as_deref(), Some("PL123"));
+    }
+
+    #[test]
+    fn keys_have_to_come_from_somewhere() {
+        let args = Args::parse_from(["feed", "--channel-id", "UC123"]);
+        assert!(resolve(&args, Config::default()).is_err());
+        assert!(parse("api_kee = \"typo\"").is_err());
+    }
+
+    #[test]
+    fn bad_config_files_dont_show_the_key() {
+        let error = parse("channel_id = \"UC123\"\napi_key = AIzaUnquoted").unwrap_err();
+        assert!(!error.contains("AIzaUnquoted"), "{error}");
+        assert!(error.starts_with("line 2, column 11"), "{error}");
+
+        // Quoted ones too, where it's the type that's wrong.
+        let error = parse("api_keys = \"AIzaQuoted\"").unwrap_err();
+        assert!(!error.contains("AIzaQuoted"), "{error}");
+        assert_eq!(error, "line 1, column 12 : problem with api_keys");
+    }
+
+    #[test]
+    fn key_files_hold_a_key_per_line() {
+        let path = std::env::temp_dir().join(format!("yt_key_{}", std::process::id()));
+        std::fs::write(&path, "# main project\nFILE_KEY\n\nSECOND_KEY\n").unwrap();
+        let args = Args::parse_from([
+            "feed".as_ref(),
+            "--channel-id".as_ref(),
+            "UC123".as_ref(),
+            "--api-key-file".as_ref(),
+            path.as_os_str(),
+        ]);
+        let settings = resolve(&args, Config::default()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(settings.api_keys, ["FILE_KEY", "SECOND_KEY"]);
+    }
+}
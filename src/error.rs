@@ -0,0 +1,202 @@
+//! The one error type for everything that can go wrong talking to youtube.
+
+use serde_json::Value;
+
+use crate::redact::redact;
+
+/// Why the API turned a request down, going off of the `reason` google gave.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ApiReason {
+    KeyInvalid,
+    QuotaExceeded,
+    RateLimited,
+    CommentsDisabled,
+    VideoNotFound,
+    ChannelNotFound,
+    PlaylistNotFound,
+    Forbidden,
+    /// Anything we don't have a special case for, as google spelled it.
+    Other(String),
+}
+
+impl ApiReason {
+    fn from_response(status: i64, json: &Value) -> Self {
+        // The useful bit is `error.errors[0].reason`. Bad keys come back as a generic
+        // "badRequest" though, with the real reason tucked away in `error.details`.
+        let reason: &str = json["error"]["errors"][0]["reason"]
+            .as_str()
+            .unwrap_or_default();
+        let detail: &str = json["error"]["details"][0]["reason"]
+            .as_str()
+            .unwrap_or_default();
+
+        match (reason, detail) {
+            ("keyInvalid", _) | (_, "API_KEY_INVALID") => Self::KeyInvalid,
+            ("quotaExceeded" | "dailyLimitExceeded", _) => Self::QuotaExceeded,
+            ("rateLimitExceeded" | "userRateLimitExceeded", _) => Self::RateLimited,
+            _ if status == 429 => Self::RateLimited,
+            ("commentsDisabled", _) => Self::CommentsDisabled,
+            ("videoNotFound", _) => Self::VideoNotFound,
+            ("channelNotFound", _) => Self::ChannelNotFound,
+            ("playlistNotFound", _) => Self::PlaylistNotFound,
+            ("forbidden", _) => Self::Forbidden,
+            (other, _) => Self::Other(other.to_string()),
+        }
+    }
+}
+
+/// Everything that can go wrong talking to youtube.
+#[derive(Debug)]
+pub enum FeedError {
+    /// Google answered, but with an error.
+    Api {
+        status: i64,
+        reason: ApiReason,
+        message: String,
+    },
+    /// Never got a proper answer. Network trouble, or the server kept erroring.
+    Transport(String),
+    /// Got an answer, but it wasn't what we expected.
+    BadResponse(String),
+    /// The channel ID doesn't belong to any channel.
+    ChannelNotFound(String),
+    /// The playlist ID doesn't belong to any playlist.
+    PlaylistNotFound(String),
+    /// The video ID doesn't belong to any video we can see.
+    VideoNotFound(String),
+}
+
+impl FeedError {
+    /// Pulls the error out of an API response, if there is one.
+    #[must_use]
+    pub fn from_response(json: &Value) -> Option<Self> {
+        let status: i64 = json["error"]["code"].as_i64()?;
+        Some(Self::Api {
+            status,
+            reason: ApiReason::from_response(status, json),
+            // Google sometimes quotes the request back at us.
+            message: redact(json["error"]["message"].as_str().unwrap_or_default()),
+        })
+    }
+
+    /// What the API said went wrong, if it was the API that said so.
+    #[must_use]
+    pub const fn reason(&self) -> Option<&ApiReason> {
+        match self {
+            Self::Api { reason, .. } => Some(reason),
+            _ => None,
+        }
+    }
+
+    /// Out of quota, or being told to slow down. Either way there's no point asking
+    /// again until [`quota::exhausted`](crate::quota::exhausted) says otherwise.
+    #[must_use]
+    pub const fn is_quota(&self) -> bool {
+        matches!(
+            self.reason(),
+            Some(ApiReason::QuotaExceeded | ApiReason::RateLimited)
+        )
+    }
+
+    /// Errors that will happen to every request, not just the one that hit it.
+    #[must_use]
+    pub fn is_fatal(&self) -> bool {
+        self.is_quota() || self.reason() == Some(&ApiReason::KeyInvalid)
+    }
+}
+
+impl std::fmt::Display for FeedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Api {
+                reason: ApiReason::KeyInvalid,
+                ..
+            } => write!(f, "Bad API key!"),
+            Self::Api {
+                reason: ApiReason::QuotaExceeded,
+                ..
+            } => write!(f, "Out of API quota!"),
+            Self::Api {
+                reason: ApiReason::RateLimited,
+                ..
+            } => write!(f, "Rate limited by the API!"),
+            Self::Api {
+                reason: ApiReason::CommentsDisabled,
+                ..
+            } => write!(f, "Comments are disabled on this video!"),
+            Self::Api {
+                status,
+                reason,
+                message,
+            } => write!(f, "API error {status} ({reason:?}) : {message}"),
+            Self::Transport(error) => write!(f, "Request failed! : {error}"),
+            Self::BadResponse(error) => write!(f, "Unexpected response! : {error}"),
+            Self::ChannelNotFound(channel_id) => write!(f, "Channel {channel_id} does not exist!"),
+            Self::PlaylistNotFound(playlist_id) => {
+                write!(f, "Playlist {playlist_id} does not exist!")
+            }
+            Self::VideoNotFound(video_id) => write!(f, "Video {video_id} does not exist!"),
+        }
+    }
+}
+
+impl std::error::Error for FeedError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn reason_of(json: &Value) -> Option<ApiReason> {
+        FeedError::from_response(json).and_then(|error| error.reason().cloned())
+    }
+
+    #[test]
+    fn successful_responses_are_not_errors() {
+        assert!(FeedError::from_response(&json!({ "items": [] })).is_none());
+    }
+
+    #[test]
+    fn reasons_are_decoded() {
+        let error = |code: i64, reason: &str| json!({ "error": { "code": code, "errors": [{ "reason": reason }] } });
+        assert_eq!(
+            reason_of(&error(403, "quotaExceeded")),
+            Some(ApiReason::QuotaExceeded)
+        );
+        assert_eq!(
+            reason_of(&error(403, "commentsDisabled")),
+            Some(ApiReason::CommentsDisabled)
+        );
+        assert_eq!(
+            reason_of(&error(404, "videoNotFound")),
+            Some(ApiReason::VideoNotFound)
+        );
+        assert_eq!(
+            reason_of(&error(403, "somethingNew")),
+            Some(ApiReason::Other("somethingNew".to_string()))
+        );
+        // Too many requests, whatever google calls it.
+        assert_eq!(
+            reason_of(&error(429, "somethingNew")),
+            Some(ApiReason::RateLimited)
+        );
+    }
+
+    #[test]
+    fn bad_keys_are_found_in_the_details() {
+        let json = json!({
+            "error": {
+                "code": 400,
+                "message": "API key not valid. Please pass a valid API key.",
+                "errors": [{ "reason": "badRequest" }],
+                "details": [{ "reason": "API_KEY_INVALID" }]
+            }
+        });
+        let Some(error) = FeedError::from_response(&json) else {
+            panic!("no error found");
+        };
+        assert_eq!(error.reason(), Some(&ApiReason::KeyInvalid));
+        assert!(error.is_fatal());
+        assert!(!error.is_quota());
+    }
+}
@@ -0,0 +1,391 @@
+// Handing out new comments one at a time, as an iterator (or a stream), so nobody has to
+// write their own polling loop.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use crate::error::FeedError;
+use crate::fetch::YTComment;
+use crate::{now, quota, Feed};
+
+/// A new comment, and the video it's on.
+#[derive(Debug, Clone)]
+pub struct CommentEvent {
+    pub video_id: String,
+    pub video_title: String,
+    pub comment: YTComment,
+}
+
+/// New comments from a [`Feed`], forever. Made with [`Feed::comments`] or by looping over a feed.
+///
+/// Waits between checks the same way the command line feed does, so `next` blocks until there's
+/// something new. Errors are handed out as they happen, and the next call picks back up after
+/// waiting the schedule's `min_delay`. A bad key ends it, since every request after that would
+/// fail the same way.
+pub struct Comments {
+    feed: Feed,
+    pending: VecDeque<CommentEvent>, // Found, but not handed out yet. Oldest first.
+    last_list_update: Option<u64>,   // When we last checked the channel for new uploads.
+    listed_at: u64,                  // When that last worked.
+    retry_at: u64,                   // Don't ask again before this, after an error.
+    stopped: bool,                   // Hit an error that will never go away.
+}
+
+impl Comments {
+    /// The feed underneath, for saving its videos or checking on them.
+    #[must_use]
+    pub const fn feed(&self) -> &Feed {
+        &self.feed
+    }
+
+    /// Stops iterating and hands the feed back. Comments found but not handed out yet are
+    /// lost.
+    #[must_use]
+    pub fn into_feed(self) -> Feed {
+        self.feed
+    }
+
+    fn failed(&mut self, error: FeedError, current_time: u64) -> FeedError {
+        // Quota trouble already waits for the reset, but anything else would get asked again
+        // straight away, and keep failing just as fast.
+        if error.is_fatal() && !error.is_quota() {
+            self.stopped = true;
+        } else {
+            self.retry_at = current_time + u64::from(self.feed.schedule.min_delay);
+        }
+        error
+    }
+
+    // The guts of `next`, giving up early once `stop` says so. Lets the stream's thread notice
+    // nobody's listening, even when there's nothing to send.
+    pub(crate) fn next_unless(
+        &mut self,
+        stop: impl Fn() -> bool,
+    ) -> Option<Result<CommentEvent, FeedError>> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Some(Ok(event));
+            }
+
+            if self.stopped || stop() {
+                return None;
+            }
+
+            let current_time: u64 = now();
+
+            // Out of quota, wait for it to reset. Or wait out the last error.
+            if quota::exhausted() || current_time < self.retry_at {
+                std::thread::sleep(Duration::from_secs(1));
+                continue;
+            }
+
+            // Check for new uploads every so often.
+            // A feed with no videos yet starts watching from now on, like the command line does.
+            let refresh_due: bool = self
+                .last_list_update
+                .is_none_or(|last| current_time.saturating_sub(last) >= self.feed.list_refresh);
+            if refresh_due {
+                // Anything found after that only shows comments from the last good look on,
+                // so an old video turning up doesn't hand out its whole history.
+                let start_timestamp: u64 = if self.feed.videos.is_empty() {
+                    current_time
+                } else {
+                    self.listed_at
+                };
+                // Even if this fails, wait a full refresh before trying again.
+                self.last_list_update = Some(current_time);
+                if let Err(error) = self.feed.refresh_videos(start_timestamp) {
+                    return Some(Err(self.failed(error, current_time)));
+                }
+                self.listed_at = current_time;
+            }
+
+            if self.feed.is_due(current_time) {
+                if let Err(error) = self.feed.poll(current_time) {
+                    return Some(Err(self.failed(error, current_time)));
+                }
+                self.take_queued();
+                continue;
+            }
+
+            std::thread::sleep(Duration::from_secs(1));
+        }
+    }
+
+    fn take_queued(&mut self) {
+        // Move everything the last poll found over to `pending`.
+        for video in &mut self.feed.videos {
+            video
+                .queued_comments
+                .sort_by_key(|comment| comment.timestamp);
+            for comment in video.queued_comments.drain(..) {
+                self.pending.push_back(CommentEvent {
+                    video_id: video.video_id.clone(),
+                    video_title: video.title.clone(),
+                    comment,
+                });
+            }
+        }
+    }
+}
+
+impl Iterator for Comments {
+    type Item = Result<CommentEvent, FeedError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_unless(|| false)
+    }
+}
+
+impl Feed {
+    /// New comments as they show up. See [`Comments`].
+    ///
+    /// Feeds that already have videos (from a saved state, or [`Feed::refresh_videos`]) carry on
+    /// from where those left off. Otherwise only comments from now on count as new. Videos that
+    /// turn up later only count comments from the look before they turned up.
+    #[must_use]
+    pub fn comments(self) -> Comments {
+        // Videos we already have don't need another trip to the channel right away.
+        let last_list_update: Option<u64> = if self.videos.is_empty() {
+            None
+        } else {
+            Some(now())
+        };
+        Comments {
+            feed: self,
+            pending: VecDeque::new(),
+            last_list_update,
+            listed_at: now(),
+            retry_at: 0,
+            stopped: false,
+        }
+    }
+
+    /// New comments as an async [`Stream`](futures::Stream). See [`Comments`].
+    ///
+    /// The polling happens on a background thread, which stops within a second or so of the
+    /// stream being dropped.
+    #[cfg(feature = "stream")]
+    #[must_use]
+    pub fn stream(self) -> CommentStream {
+        let (sender, receiver) = futures::channel::mpsc::unbounded();
+        std::thread::spawn(move || {
+            let mut comments: Comments = self.comments();
+            // Checked between every poll, so a quiet channel doesn't keep spending quota on
+            // a stream nobody has anymore.
+            while let Some(event) = comments.next_unless(|| sender.is_closed()) {
+                // Nobody's listening anymore.
+                if sender.unbounded_send(event).is_err() {
+                    break;
+                }
+            }
+        });
+        CommentStream { receiver }
+    }
+}
+
+impl IntoIterator for Feed {
+    type Item = Result<CommentEvent, FeedError>;
+    type IntoIter = Comments;
+
+    fn into_iter(self) -> Comments {
+        self.comments()
+    }
+}
+
+/// New comments from a [`Feed`], as an async stream. Made with [`Feed::stream`].
+#[cfg(feature = "stream")]
+pub struct CommentStream {
+    receiver: futures::channel::mpsc::UnboundedReceiver<Result<CommentEvent, FeedError>>,
+}
+
+#[cfg(feature = "stream")]
+impl futures::Stream for CommentStream {
+    type Item = Result<CommentEvent, FeedError>;
+
+    fn poll_next(
+        mut self: std::pin::Pin<&mut Self>,
+        context: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Self::Item>> {
+        std::pin::Pin::new(&mut self.receiver).poll_next(context)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ApiReason;
+    use crate::fixtures::{comment, rfc3339, thread, FixtureClient, T};
+    use crate::TrackedVideo;
+    use serde_json::json;
+
+    fn feed(client: FixtureClient) -> Feed {
+        let mut feed = Feed::new("KEY", "UC123");
+        feed.client = Box::new(client);
+        for video_id in ["a", "b"] {
+            feed.videos.push(TrackedVideo {
+                title: format!("Video {video_id}"),
+                video_id: video_id.to_string(),
+                channel_id: "UC123".to_string(),
+                most_recent_timestamp: T,
+                queued_comments: Vec::new(),
+                recheck_delay: 10,
+                next_check: 0,
+                reply_counts: std::collections::HashMap::new(),
+                seen_ids: VecDeque::new(),
+                comments_disabled: false,
+            });
+        }
+        feed
+    }
+
+    #[test]
+    fn comments_come_out_oldest_first_per_video() {
+        let client = FixtureClient::new()
+            .with(
+                "videoId=a",
+                &json!({
+                    "items": [
+                        thread("a2", T + 20, 0, &[]),
+                        thread("a1", T + 10, 1, &[comment("a1r", "someone", "hi", T + 30)]),
+                    ]
+                }),
+            )
+            .with(
+                "videoId=b",
+                &json!({ "items": [thread("b1", T + 5, 0, &[])] }),
+            );
+
+        let events: Vec<CommentEvent> = feed(client)
+            .into_iter()
+            .take(4)
+            .collect::<Result<_, _>>()
+            .unwrap();
+
+        let ids: Vec<(&str, &str)> = events
+            .iter()
+            .map(|event| (event.video_id.as_str(), event.comment.id.as_str()))
+            .collect();
+        assert_eq!(ids, [("a", "a1"), ("a", "a2"), ("a", "a1r"), ("b", "b1")]);
+        assert_eq!(events[3].video_title, "Video b");
+    }
+
+    #[test]
+    fn errors_are_handed_out() {
+        let body = json!({
+            "error": {
+                "code": 400,
+                "message": "API key not valid.",
+                "errors": [{ "reason": "keyInvalid" }]
+            }
+        });
+        let client = FixtureClient::new().with("commentThreads?", &body);
+        let mut comments = feed(client).comments();
+        let error = comments.next().unwrap().unwrap_err();
+        assert_eq!(error.reason(), Some(&ApiReason::KeyInvalid));
+        // Nothing got lost along the way.
+        assert_eq!(comments.feed().videos.len(), 2);
+        // It's not going to get any better.
+        assert!(comments.next().is_none());
+    }
+
+    #[test]
+    fn errors_are_waited_out() {
+        // Only the channel is broken, so this will pass eventually.
+        let mut feed = Feed::new("KEY", "UC123");
+        feed.client = Box::new(FixtureClient::new().failing("channels?"));
+        let mut comments = feed.comments();
+        let error = comments.next().unwrap().unwrap_err();
+        assert!(matches!(error, FeedError::Transport(_)));
+        assert!(comments.retry_at > now());
+        assert!(!comments.stopped);
+    }
+
+    #[test]
+    fn empty_feeds_start_from_now() {
+        let later: u64 = now() + 1000;
+        let client = FixtureClient::new()
+            .with(
+                "channels?",
+                &json!({
+                    "items": [{ "contentDetails": { "relatedPlaylists": { "uploads": "UU123" } } }]
+                }),
+            )
+            .with(
+                "playlistItems?",
+                &json!({
+                    "items": [{
+                        "snippet": { "title": "Video c" },
+                        "contentDetails": { "videoId": "c", "videoPublishedAt": rfc3339(T) }
+                    }]
+                }),
+            )
+            .with(
+                "commentThreads?",
+                &json!({ "items": [thread("new", later, 0, &[]), thread("old", T, 0, &[])] }),
+            );
+        let mut feed = Feed::new("KEY", "UC123");
+        feed.client = Box::new(client);
+
+        let event: CommentEvent = feed.comments().next().unwrap().unwrap();
+        assert_eq!(event.video_id, "c");
+        assert_eq!(event.comment.id, "new");
+    }
+
+    #[test]
+    fn videos_turning_up_later_skip_their_history() {
+        let later: u64 = now() + 1000;
+        let item = |id: &str| {
+            json!({
+                "snippet": { "title": format!("Video {id}") },
+                "contentDetails": { "videoId": id, "videoPublishedAt": rfc3339(T) }
+            })
+        };
+        let client = FixtureClient::new()
+            .with(
+                "channels?",
+                &json!({
+                    "items": [{ "contentDetails": { "relatedPlaylists": { "uploads": "UU123" } } }]
+                }),
+            )
+            .with(
+                "playlistItems?",
+                &json!({ "items": [item("a"), item("b"), item("c")] }),
+            )
+            .with(
+                "videoId=c",
+                &json!({ "items": [thread("new", later, 0, &[]), thread("old", T, 0, &[])] }),
+            )
+            .with("commentThreads?", &json!({ "items": [] }));
+        let mut feed = feed(client);
+        feed.list_refresh = 0;
+
+        let event: CommentEvent = feed.comments().next().unwrap().unwrap();
+        assert_eq!(event.video_id, "c");
+        assert_eq!(event.comment.id, "new");
+    }
+
+    #[cfg(feature = "stream")]
+    #[test]
+    fn streams_match_the_iterator() {
+        use futures::StreamExt;
+
+        let client = FixtureClient::new()
+            .with(
+                "videoId=a",
+                &json!({ "items": [thread("a1", T + 10, 0, &[])] }),
+            )
+            .with(
+                "videoId=b",
+                &json!({ "items": [thread("b1", T + 5, 0, &[])] }),
+            );
+
+        let events: Vec<Result<CommentEvent, FeedError>> =
+            futures::executor::block_on(feed(client).stream().take(2).collect());
+        let ids: Vec<String> = events
+            .into_iter()
+            .map(|event| event.unwrap().comment.id)
+            .collect();
+        assert_eq!(ids, ["a1", "b1"]);
+    }
+}
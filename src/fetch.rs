@@ -0,0 +1,1083 @@
+// Talking to the youtube API: looking up channels, their videos, and the comments on them.
+
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+
+use chrono::DateTime;
+use serde_json::Value;
+
+use crate::channel::{encode, ChannelInfo, ChannelRef};
+use crate::error::{ApiReason, FeedError};
+use crate::http::HttpClient;
+use crate::notice::{self, Notice};
+use crate::{quota, API_URL};
+
+/// Checks that the API key works and the channel exists, and returns its ID and name.
+///
+/// `channel` can be anything [`ChannelRef::parse`] understands, like an `@handle`, a channel
+/// URL, or a link to one of its videos. Those get looked up, which is how to find out their
+/// `UC...` ID.
+///
+/// # Errors
+///
+/// [`FeedError::ChannelNotFound`] if there's no such channel, or whatever went wrong with the
+/// request, like a bad key.
+pub fn test_channel_and_key(
+    client: &dyn HttpClient,
+    channel: &str,
+    key: &str,
+) -> Result<ChannelInfo, FeedError> {
+    // Is this channel real?
+    let Some(channel_ref) = ChannelRef::parse(channel) else {
+        return Err(FeedError::ChannelNotFound(channel.to_string()));
+    };
+
+    // A video stands for whoever uploaded it.
+    let channel_ref: ChannelRef = match channel_ref {
+        ChannelRef::Video(video_id) => match uploader_of(client, &video_id, key)? {
+            Some(channel_id) => ChannelRef::Id(channel_id),
+            None => return Err(FeedError::ChannelNotFound(channel.to_string())),
+        },
+        other => other,
+    };
+
+    // Build test URLs:
+
+    // Base URL
+    let base_url = "channels";
+
+    // Query parameters
+    let part_param = "part=snippet";
+    let fields_param = "fields=items(id,snippet(title))";
+
+    // API Key
+    let api_key = format!("&key={}", &key);
+
+    // Handles and such might take a couple of tries.
+    for lookup_param in channel_ref.lookups() {
+        // Combine the parts to create the full query
+        let query =
+            format!("{API_URL}{base_url}?{part_param}&{lookup_param}&{fields_param}&{api_key}");
+
+        // Bad keys and quota trouble come back as errors.
+        let json: Value = api_get(client, &query)?;
+
+        //All good! return the channel.
+        // Unknown channels don't error, they just come back with no items.
+        let channel: &Value = &json["items"][0];
+        if let (Some(id), Some(name)) =
+            (channel["id"].as_str(), channel["snippet"]["title"].as_str())
+        {
+            return Ok(ChannelInfo {
+                id: id.to_string(),
+                name: name.to_string(),
+            });
+        }
+    }
+
+    // No title means no channel.
+    Err(FeedError::ChannelNotFound(channel.to_string()))
+}
+
+fn uploader_of(
+    client: &dyn HttpClient,
+    video_id: &str,
+    key: &str,
+) -> Result<Option<String>, FeedError> {
+    // The ID of the channel that posted a video, if there is such a video.
+    let query: String = format!(
+        "{API_URL}videos?part=snippet&id={}&fields=items(snippet(channelId))&key={key}",
+        encode(video_id)
+    );
+    let json: Value = api_get(client, &query)?;
+    Ok(json["items"][0]["snippet"]["channelId"]
+        .as_str()
+        .map(ToString::to_string))
+}
+
+fn api_get(client: &dyn HttpClient, url: &str) -> Result<Value, FeedError> {
+    // Runs a query and cracks open the JSON, turning any error google sent back into a FeedError.
+    let body: String = client.get(url)?;
+    parse_response(&body)
+}
+
+fn api_get_if_changed(client: &dyn HttpClient, url: &str) -> Result<Option<Value>, FeedError> {
+    // Same as api_get, but None if nothing changed since the last time we asked.
+    let Some(body) = client.get_if_changed(url)? else {
+        return Ok(None);
+    };
+    parse_response(&body).map(Some)
+}
+
+fn parse_response(body: &str) -> Result<Value, FeedError> {
+    let json: Value = match serde_json::from_str(body) {
+        Ok(okay) => okay,
+        Err(e) => return Err(FeedError::BadResponse(format!("Bad JSON! : {e}"))),
+    };
+
+    if let Some(error) = FeedError::from_response(&json) {
+        // Out of quota, stop polling until it resets. Going too fast only needs a breather.
+        match error.reason() {
+            Some(ApiReason::QuotaExceeded) => quota::mark_exhausted(),
+            Some(ApiReason::RateLimited) => quota::mark_rate_limited(),
+            _ => (),
+        }
+        return Err(error);
+    }
+
+    Ok(json)
+}
+
+/// A comment or reply on a video.
+#[derive(Debug, Clone)]
+pub struct YTComment {
+    /// Youtube's ID for this comment.
+    pub id: String,
+    /// The comment text, as written.
+    pub content: String,
+    pub author_name: String,
+    /// Missing for some older or deleted accounts.
+    pub author_channel_id: Option<String>,
+    pub like_count: u64,
+    /// When it was posted, as a unix timestamp.
+    pub timestamp: u64,
+    /// The comment thread this belongs to. Top level comments share their thread's ID.
+    pub thread_id: String,
+    /// Who started the thread, if this is a reply.
+    pub reply_to: Option<String>,
+}
+
+/// Grabs the comments and replies on a video posted since `since`, newest threads first.
+///
+/// Pages through at most `max_pages` pages of 100 threads, sending a
+/// [`Notice::PagesRanOut`] if new comments didn't stop before then. `known_replies` is the reply
+/// count of each thread from earlier checks (empty for a first check), and threads whose
+/// count changed get their full reply list pulled. Returns the comments, and the reply
+/// counts of the threads it looked at, to go into `known_replies` next time.
+///
+/// The first page is only handed over if it changed since the last check (see
+/// [`HttpClient::get_if_changed`]). If it didn't, there's nothing new: no comments, and the
+/// same reply counts as before.
+///
+/// # Errors
+///
+/// Any failed request, or a response that couldn't be made sense of.
+pub fn get_comments_from_video<S: BuildHasher>(
+    client: &dyn HttpClient,
+    key: &str,
+    video_id: &str,
+    since: u64,
+    max_pages: u16,
+    known_replies: &HashMap<String, u64, S>,
+) -> Result<(Vec<YTComment>, HashMap<String, u64>), FeedError> {
+    let result = comments_since(client, key, video_id, since, max_pages, known_replies);
+    // If we fell over partway, the first page came back fine but none of it got used.
+    // Make sure it doesn't count as old news next time.
+    if result.is_err() {
+        client.forget(&comment_threads_url(key, video_id, None));
+    }
+    result
+}
+
+/// The URL for a page of comment threads on a video. `None` for the first page.
+#[must_use]
+pub fn comment_threads_url(key: &str, video_id: &str, page_token: Option<&str>) -> String {
+    let rq_type = "commentThreads?";
+    let key_param = format!("key={key}&");
+    let format = "textFormat=plainText&";
+    let part = "part=snippet%2Creplies&";
+    let order = "order=time&";
+    let vid_id = format!("videoId={video_id}&");
+    let num_results = "maxResults=100";
+    let page = page_token.map_or_else(String::new, |token| format!("&pageToken={token}"));
+    let fields = "&fields=nextPageToken%2Citems(id%2Csnippet(totalReplyCount%2CtopLevelComment(id%2Csnippet(authorDisplayName%2CauthorChannelId%2CtextOriginal%2ClikeCount%2CpublishedAt)))%2Creplies(comments(id%2Csnippet(authorDisplayName%2CauthorChannelId%2CtextOriginal%2ClikeCount%2CpublishedAt))))";
+    format!("{API_URL}{rq_type}{key_param}{format}{part}{order}{vid_id}{num_results}{page}{fields}")
+}
+
+fn comments_since<S: BuildHasher>(
+    client: &dyn HttpClient,
+    key: &str,
+    video_id: &str,
+    since: u64,
+    max_pages: u16,
+    known_replies: &HashMap<String, u64, S>,
+) -> Result<(Vec<YTComment>, HashMap<String, u64>), FeedError> {
+    //TODO: Filter out comments from self
+    //https://www.googleapis.com/youtube/v3/commentThreads?key=[KEY]&textFormat=plainText&part=snippet&videoId=[VIDEO_ID]&maxResults=[AMOUNT]]
+
+    // Comments come back newest first, so we keep grabbing pages until we find one
+    // older than `since`, run out of pages, or hit `max_pages`.
+
+    // Replies come along with their threads, so we only see new replies on threads that
+    // land in the pages we grab. The first page is always grabbed, so the newest 100
+    // threads are always covered.
+
+    // `known_replies` is the reply count of each thread from last time. The API only
+    // inlines a few replies per thread, so threads whose count changed get their full
+    // reply list pulled. The counts from this check are returned for next time.
+
+    let mut return_vec: Vec<YTComment> = Vec::new();
+    let mut reply_counts: HashMap<String, u64> = HashMap::new();
+    let mut page_token: Option<String> = None;
+
+    for page_number in 0..max_pages {
+        // Create the Curl address.
+        let url: String = comment_threads_url(key, video_id, page_token.as_deref());
+
+        // The newest comments are all on the first page, so if that hasn't changed,
+        // nothing has.
+        let unwrapped_json: Value = if page_number == 0 {
+            let Some(json) = api_get_if_changed(client, &url)? else {
+                let unchanged: HashMap<String, u64> = known_replies
+                    .iter()
+                    .map(|(thread_id, count)| (thread_id.clone(), *count))
+                    .collect();
+                return Ok((Vec::new(), unchanged));
+            };
+            json
+        } else {
+            api_get(client, &url)?
+        };
+
+        // Okay, now that we know we have a good comment pull, lets scrape those comments out!
+
+        // Json structure is as follows:
+        //{
+        //"nextPageToken": "TOKEN",
+        //"items": [
+        //  {
+        //    "id": "THREAD_ID",
+        //    "snippet": {
+        //      "totalReplyCount": 0,
+        //      "topLevelComment": {
+        //        "id": "COMMENT_ID",
+        //        "snippet": {
+        //          "textOriginal": "TEXT",
+        //          "authorDisplayName": "NAME",
+        //          "authorChannelId": { "value": "CHANNEL_ID" },
+        //          "likeCount": 0,
+        //          "publishedAt": "TIME"
+        //        }
+        //      }
+        //    },
+        //    "replies": {
+        //      "comments": [
+        //        {
+        //          "id": "COMMENT_ID",
+        //          "snippet": { same as above }
+        //        },
+        //      ]
+        //    }
+        //  },
+
+        let Some(items_array) = unwrapped_json["items"].as_array() else {
+            // No items at all means no more comments.
+            break;
+        };
+
+        // Set once we see a comment we already know about.
+        let mut reached_old = false;
+
+        for item in items_array {
+            let thread_id: &str = item["id"].as_str().unwrap_or_default();
+            let snippet = &item["snippet"];
+
+            let top_level: YTComment = parse_comment(&snippet["topLevelComment"], thread_id, None)?;
+
+            if top_level.timestamp < since {
+                reached_old = true;
+            }
+
+            // Now for the replies.
+            let total_replies: u64 = snippet["totalReplyCount"].as_u64().unwrap_or_default();
+            let inlined: &[Value] = item["replies"]["comments"]
+                .as_array()
+                .map_or(&[], Vec::as_slice);
+
+            // Only pull the whole list if the inlined ones might not be all of the new ones.
+            // Threads we haven't seen before only count if the thread itself is new.
+            let changed: bool = known_replies
+                .get(thread_id)
+                .map_or(top_level.timestamp >= since, |&known| {
+                    known != total_replies
+                });
+
+            let mut replies: Vec<YTComment> = if changed && total_replies > inlined.len() as u64 {
+                get_replies_from_thread(client, key, thread_id, &top_level.author_name, max_pages)?
+            } else {
+                let mut inlined_replies: Vec<YTComment> = Vec::new();
+                for reply in inlined {
+                    inlined_replies.push(parse_comment(
+                        reply,
+                        thread_id,
+                        Some(&top_level.author_name),
+                    )?);
+                }
+                inlined_replies
+            };
+
+            reply_counts.insert(thread_id.to_string(), total_replies);
+
+            // push that comment! and its replies!
+            return_vec.push(top_level);
+            return_vec.append(&mut replies);
+        }
+
+        // Anything past here is older still, no need to keep going.
+        if reached_old {
+            break;
+        }
+
+        // Onto the next page, if there is one.
+        match unwrapped_json["nextPageToken"].as_str() {
+            Some(token) => page_token = Some(token.to_string()),
+            None => break,
+        }
+
+        // Out of pages with new comments still going, so the rest of them get missed.
+        if page_number + 1 == max_pages {
+            notice::send(&Notice::PagesRanOut {
+                video_id: video_id.to_string(),
+                max_pages,
+            });
+        }
+    }
+
+    Ok((return_vec, reply_counts))
+}
+
+/// Grabs every reply on a comment thread, oldest first. `parent_author` ends up in each
+/// reply's [`YTComment::reply_to`].
+///
+/// # Errors
+///
+/// Any failed request, or a response that couldn't be made sense of.
+pub fn get_replies_from_thread(
+    client: &dyn HttpClient,
+    key: &str,
+    thread_id: &str,
+    parent_author: &str,
+    max_pages: u16,
+) -> Result<Vec<YTComment>, FeedError> {
+    // Grabs every reply on a comment thread, for when there's too many to come inlined.
+    //https://www.googleapis.com/youtube/v3/comments?key=[KEY]&textFormat=plainText&part=snippet&parentId=[THREAD_ID]&maxResults=100
+
+    let mut return_vec: Vec<YTComment> = Vec::new();
+    let mut page_token: Option<String> = None;
+
+    for _ in 0..max_pages {
+        // Create the Curl address.
+        let rq_type = "comments?";
+        let key_param = format!("key={key}&");
+        let format = "textFormat=plainText&";
+        let part = "part=snippet&";
+        let parent = format!("parentId={thread_id}&");
+        let num_results = "maxResults=100";
+        let page = page_token.map_or_else(String::new, |token| format!("&pageToken={token}"));
+        let fields =
+            "&fields=nextPageToken%2Citems(id%2Csnippet(authorDisplayName%2CauthorChannelId%2CtextOriginal%2ClikeCount%2CpublishedAt))";
+        let url = format!(
+            "{API_URL}{rq_type}{key_param}{format}{part}{parent}{num_results}{page}{fields}"
+        );
+
+        let unwrapped_json: Value = api_get(client, &url)?;
+
+        // Json structure is as follows:
+        //{
+        //"nextPageToken": "TOKEN",
+        //"items": [
+        //  {
+        //    "id": "COMMENT_ID",
+        //    "snippet": {
+        //      "textOriginal": "TEXT",
+        //      "authorDisplayName": "NAME",
+        //      "authorChannelId": { "value": "CHANNEL_ID" },
+        //      "likeCount": 0,
+        //      "publishedAt": "TIME"
+        //    }
+        //  },
+
+        let Some(items_array) = unwrapped_json["items"].as_array() else {
+            break;
+        };
+
+        for item in items_array {
+            return_vec.push(parse_comment(item, thread_id, Some(parent_author))?);
+        }
+
+        // Onto the next page, if there is one.
+        match unwrapped_json["nextPageToken"].as_str() {
+            Some(token) => page_token = Some(token.to_string()),
+            None => break,
+        }
+    }
+
+    Ok(return_vec)
+}
+
+fn parse_comment(
+    comment: &Value,
+    thread_id: &str,
+    reply_to: Option<&str>,
+) -> Result<YTComment, FeedError> {
+    // Pull a comment out of its JSON. Top level comments and replies look the same.
+    let snippet = &comment["snippet"];
+    Ok(YTComment {
+        id: comment["id"].as_str().unwrap_or_default().to_string(),
+        content: snippet["textOriginal"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string(),
+        author_name: snippet["authorDisplayName"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string(),
+        author_channel_id: snippet["authorChannelId"]["value"]
+            .as_str()
+            .map(str::to_string),
+        like_count: snippet["likeCount"].as_u64().unwrap_or_default(),
+        timestamp: parse_timestamp(snippet["publishedAt"].as_str()).ok_or_else(|| {
+            FeedError::BadResponse(format!(
+                "Bad comment timestamp! : {}",
+                snippet["publishedAt"]
+            ))
+        })?,
+        thread_id: thread_id.to_string(),
+        reply_to: reply_to.map(str::to_string),
+    })
+}
+
+// Turn one of youtube's RFC 3339 timestamps into a unix timestamp.
+fn parse_timestamp(time_string: Option<&str>) -> Option<u64> {
+    let timestamp = DateTime::parse_from_rfc3339(time_string?).ok()?;
+    timestamp.timestamp().try_into().ok()
+}
+
+/// A public video on a channel.
+#[derive(Debug, Clone)]
+pub struct Video {
+    pub title: String,
+    pub id: String,
+    /// The channel it was uploaded to.
+    pub channel_id: String,
+    /// Upload time, as a unix timestamp.
+    pub published: u64,
+}
+
+/// Lists every public video on a channel, newest first, if the list changed since the
+/// last time this was called. `None` means it didn't. Channels with no uploads give an
+/// empty list.
+///
+/// # Errors
+///
+/// Any failed request.
+pub fn get_videos_from_channel(
+    client: &dyn HttpClient,
+    key: &str,
+    channel_id: &str,
+) -> Result<Option<Vec<Video>>, FeedError> {
+    // Lets get those videos
+    // Every channel has an "uploads" playlist with all of its videos in it, which is
+    // way cheaper to page through than searching the channel.
+    // No uploads playlist, no videos.
+    let Some(playlist_id) = get_uploads_playlist(client, key, channel_id)? else {
+        return Ok(Some(Vec::new()));
+    };
+    list_playlist(client, key, &playlist_id, true)
+}
+
+/// Finds the playlist holding every upload on a channel, if it has one.
+///
+/// # Errors
+///
+/// Any failed request.
+pub fn get_uploads_playlist(
+    client: &dyn HttpClient,
+    key: &str,
+    channel_id: &str,
+) -> Result<Option<String>, FeedError> {
+    // Create the URL for the API request
+    let function = "channels?part=contentDetails";
+    let fields = "&fields=items(contentDetails(relatedPlaylists(uploads)))";
+    let api_key = format!("&key={key}");
+    let channel_param = format!("&id={channel_id}");
+    let query = format!("{API_URL}{function}{fields}{api_key}{channel_param}");
+
+    let unwrapped_json: Value = api_get(client, &query)?;
+
+    // return format should match
+    // {
+    //   "items": [
+    //     {
+    //       "contentDetails": {
+    //         "relatedPlaylists": {
+    //           "uploads": "PLAYLIST_ID"
+    //         }
+    //       }
+    //     }
+    //   ]
+    // }
+
+    let uploads = &unwrapped_json["items"][0]["contentDetails"]["relatedPlaylists"]["uploads"];
+    Ok(uploads.as_str().map(str::to_string))
+}
+
+/// Lists every public video in a playlist, newest first. A missing playlist gives an empty
+/// list.
+///
+/// # Errors
+///
+/// Any failed request.
+pub fn get_videos_from_playlist(
+    client: &dyn HttpClient,
+    key: &str,
+    playlist_id: &str,
+) -> Result<Vec<Video>, FeedError> {
+    // Not asking if it changed, so there's always a list.
+    Ok(list_playlist(client, key, playlist_id, false)?.unwrap_or_default())
+}
+
+fn list_playlist(
+    client: &dyn HttpClient,
+    key: &str,
+    playlist_id: &str,
+    only_if_changed: bool,
+) -> Result<Option<Vec<Video>>, FeedError> {
+    let result = playlist_pages(client, key, playlist_id, only_if_changed);
+    // Same as with comments, a first page we never got to use shouldn't count as seen.
+    if only_if_changed && result.is_err() {
+        client.forget(&playlist_items_url(key, playlist_id, None));
+    }
+    result
+}
+
+fn playlist_items_url(key: &str, playlist_id: &str, page_token: Option<&str>) -> String {
+    // Create the URL for the API request
+    let function = "playlistItems?part=snippet%2CcontentDetails";
+    let max_results = "&maxResults=50";
+    let fields = "&fields=nextPageToken%2Citems(snippet(title%2CvideoOwnerChannelId)%2CcontentDetails(videoId%2CvideoPublishedAt))";
+    let api_key = format!("&key={key}");
+    let playlist_param = format!("&playlistId={playlist_id}");
+    let page = page_token.map_or_else(String::new, |token| format!("&pageToken={token}"));
+    format!("{API_URL}{function}{max_results}{fields}{api_key}{playlist_param}{page}")
+}
+
+fn playlist_pages(
+    client: &dyn HttpClient,
+    key: &str,
+    playlist_id: &str,
+    only_if_changed: bool,
+) -> Result<Option<Vec<Video>>, FeedError> {
+    // Grab every video in a playlist, 50 at a time (the most the API will give us).
+
+    let mut return_vec: Vec<Video> = Vec::new();
+    let mut page_token: Option<String> = None;
+    let bad_chars = &['\"']; // Dont want these in our titles
+
+    loop {
+        let query: String = playlist_items_url(key, playlist_id, page_token.as_deref());
+
+        // New uploads land on the first page, so if that hasn't changed, nothing has.
+        let first_page: bool = page_token.is_none();
+        let response: Result<Value, FeedError> = if only_if_changed && first_page {
+            match api_get_if_changed(client, &query) {
+                Ok(Some(json)) => Ok(json),
+                Ok(None) => return Ok(None),
+                Err(error) => Err(error),
+            }
+        } else {
+            api_get(client, &query)
+        };
+
+        // Empty channels have no uploads playlist to find.
+        let unwrapped_json: Value = match response {
+            Ok(okay) => okay,
+            Err(error) if error.reason() == Some(&ApiReason::PlaylistNotFound) => break,
+            Err(error) => return Err(error),
+        };
+
+        // return format should match
+        // {
+        //   "nextPageToken": "TOKEN",
+        //   "items": [
+        //     {
+        //       "snippet": {
+        //         "title": "TITLE"
+        //       },
+        //       "contentDetails": {
+        //         "videoId": "ID",
+        //         "videoPublishedAt": "TIME"
+        //       }
+        //     },
+        // }
+
+        let Some(items_array) = unwrapped_json["items"].as_array() else {
+            break;
+        };
+
+        // Pull those titles and ID's out!
+
+        for item in items_array {
+            // check for nulls
+            // Private and deleted videos dont have a publish time, we cant read those anyways.
+            if item["snippet"]["title"] == Value::Null
+                || item["contentDetails"]["videoId"] == Value::Null
+                || item["contentDetails"]["videoPublishedAt"] == Value::Null
+            {
+                // Nulls are a no-no, skip
+                continue;
+            }
+            let wrapped: Video = Video {
+                title: item["snippet"]["title"]
+                    .to_string()
+                    .trim()
+                    .replace(bad_chars, ""),
+                id: item["contentDetails"]["videoId"]
+                    .to_string()
+                    .trim()
+                    .replace(bad_chars, ""),
+                channel_id: item["snippet"]["videoOwnerChannelId"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string(),
+                published: parse_timestamp(item["contentDetails"]["videoPublishedAt"].as_str())
+                    .unwrap_or_default(),
+            };
+            // onto the vec it goes
+            return_vec.push(wrapped);
+        }
+
+        // Onto the next page, if there is one.
+        match unwrapped_json["nextPageToken"].as_str() {
+            Some(token) => page_token = Some(token.to_string()),
+            None => break,
+        }
+    }
+
+    // Newest first.
+    return_vec.sort_by_key(|video| std::cmp::Reverse(video.published));
+    Ok(Some(return_vec))
+}
+
+/// Checks that the API key works and the playlist exists, and returns the playlist's name.
+///
+/// # Errors
+///
+/// [`FeedError::PlaylistNotFound`] if there's no such playlist, or whatever went wrong with
+/// the request.
+pub fn test_playlist_and_key(
+    client: &dyn HttpClient,
+    playlist_id: &str,
+    key: &str,
+) -> Result<String, FeedError> {
+    let function = "playlists?part=snippet";
+    let fields = "&fields=items(snippet(title))";
+    let api_key = format!("&key={key}");
+    let playlist_param = format!("&id={}", encode(playlist_id));
+    let query = format!("{API_URL}{function}{fields}{api_key}{playlist_param}");
+
+    // Same as channels, unknown playlists just come back with no items.
+    let json: Value = api_get(client, &query)?;
+    json["items"][0]["snippet"]["title"].as_str().map_or_else(
+        || Err(FeedError::PlaylistNotFound(playlist_id.to_string())),
+        |title| Ok(title.to_string()),
+    )
+}
+
+/// Looks up videos by ID, in no particular order. Private and deleted videos, and IDs that
+/// aren't videos at all, are left out.
+///
+/// # Errors
+///
+/// Any failed request.
+pub fn get_videos_by_id(
+    client: &dyn HttpClient,
+    key: &str,
+    video_ids: &[String],
+) -> Result<Vec<Video>, FeedError> {
+    let mut return_vec: Vec<Video> = Vec::new();
+    // 50 at a time, the most the API will take.
+    for chunk in video_ids.chunks(50) {
+        let function = "videos?part=snippet";
+        let fields = "&fields=items(id%2Csnippet(title%2CchannelId%2CpublishedAt))";
+        let api_key = format!("&key={key}");
+        let ids: Vec<String> = chunk.iter().map(|id| encode(id)).collect();
+        let id_param = format!("&id={}", ids.join("%2C"));
+        let query = format!("{API_URL}{function}{fields}{api_key}{id_param}");
+
+        let json: Value = api_get(client, &query)?;
+
+        // return format should match
+        // {
+        //   "items": [
+        //     {
+        //       "id": "ID",
+        //       "snippet": {
+        //         "title": "TITLE",
+        //         "channelId": "CHANNEL_ID",
+        //         "publishedAt": "TIME"
+        //       }
+        //     }
+        //   ]
+        // }
+
+        let Some(items_array) = json["items"].as_array() else {
+            continue;
+        };
+        for item in items_array {
+            let (Some(id), Some(title)) = (item["id"].as_str(), item["snippet"]["title"].as_str())
+            else {
+                continue;
+            };
+            return_vec.push(Video {
+                title: title.trim().replace('"', ""),
+                id: id.to_string(),
+                channel_id: item["snippet"]["channelId"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string(),
+                published: parse_timestamp(item["snippet"]["publishedAt"].as_str())
+                    .unwrap_or_default(),
+            });
+        }
+    }
+    Ok(return_vec)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{api_error, comment, heard, listen, rfc3339, thread, FixtureClient, T};
+    use serde_json::json;
+
+    #[test]
+    fn channel_test_returns_the_channel_name() {
+        let client = FixtureClient::new().with(
+            "channels?",
+            &json!({ "items": [{ "id": "UC123", "snippet": { "title": "Some Channel" } }] }),
+        );
+        let channel = test_channel_and_key(&client, "UC123", "KEY").unwrap();
+        assert_eq!(channel.name, "Some Channel");
+        assert_eq!(channel.id, "UC123");
+    }
+
+    #[test]
+    fn custom_urls_fall_back_to_usernames() {
+        let client = FixtureClient::new()
+            .with("forHandle=someone", &json!({}))
+            .with(
+                "forUsername=someone",
+                &json!({ "items": [{ "id": "UC456", "snippet": { "title": "Someone" } }] }),
+            );
+        let channel =
+            test_channel_and_key(&client, "https://www.youtube.com/c/someone", "KEY").unwrap();
+        assert_eq!(channel.id, "UC456");
+        assert_eq!(client.requests().len(), 2);
+
+        let error =
+            test_channel_and_key(&client, "youtube.com/playlist?list=abc", "KEY").unwrap_err();
+        assert!(matches!(error, FeedError::ChannelNotFound(_)));
+    }
+
+    #[test]
+    fn ids_are_encoded_into_urls() {
+        let client = FixtureClient::new()
+            .with(
+                "playlists?",
+                &json!({ "items": [{ "snippet": { "title": "List" } }] }),
+            )
+            .with("videos?", &json!({ "items": [] }));
+        test_playlist_and_key(&client, "PL 1&x", "KEY").unwrap();
+        get_videos_by_id(&client, "KEY", &["a&b".to_string(), "c".to_string()]).unwrap();
+        let requests = client.requests();
+        assert!(requests[0].ends_with("&id=PL%201%26x"), "{}", requests[0]);
+        assert!(requests[1].ends_with("&id=a%26b%2Cc"), "{}", requests[1]);
+    }
+
+    #[test]
+    fn video_links_find_their_uploader() {
+        let client = FixtureClient::new()
+            .with("id=missing", &json!({}))
+            .with(
+                "videos?",
+                &json!({ "items": [{ "snippet": { "channelId": "UC789" } }] }),
+            )
+            .with(
+                "channels?part=snippet&id=UC789",
+                &json!({ "items": [{ "id": "UC789", "snippet": { "title": "Uploader" } }] }),
+            );
+        let channel =
+            test_channel_and_key(&client, "https://www.youtube.com/watch?v=abc", "KEY").unwrap();
+        assert_eq!(channel.id, "UC789");
+        assert_eq!(channel.name, "Uploader");
+        assert!(client.requests()[0].contains("videos?part=snippet&id=abc&"));
+
+        let error = test_channel_and_key(&client, "youtu.be/missing", "KEY").unwrap_err();
+        assert!(matches!(error, FeedError::ChannelNotFound(_)));
+    }
+
+    #[test]
+    fn channel_test_with_no_items_is_an_unknown_channel() {
+        let client = FixtureClient::new().with("channels?", &json!({}));
+        let error = test_channel_and_key(&client, "UC123", "KEY").unwrap_err();
+        assert!(matches!(error, FeedError::ChannelNotFound(id) if id == "UC123"));
+    }
+
+    #[test]
+    fn channel_test_reports_bad_keys() {
+        let body = json!({
+            "error": {
+                "code": 400,
+                "message": "API key not valid. Please pass a valid API key.",
+                "errors": [{ "reason": "badRequest" }],
+                "details": [{ "reason": "API_KEY_INVALID" }]
+            }
+        });
+        let client = FixtureClient::new().with("channels?", &body);
+        let error = test_channel_and_key(&client, "UC123", "KEY").unwrap_err();
+        assert_eq!(error.reason(), Some(&ApiReason::KeyInvalid));
+        assert!(error.is_fatal());
+    }
+
+    #[test]
+    fn network_failures_are_transport_errors() {
+        let client = FixtureClient::new().failing("channels?");
+        let error = test_channel_and_key(&client, "UC123", "KEY").unwrap_err();
+        assert!(matches!(error, FeedError::Transport(_)));
+    }
+
+    #[test]
+    fn garbage_bodies_are_bad_responses() {
+        let client = FixtureClient::new().with_raw("channels?", "<html>oops</html>");
+        let error = test_channel_and_key(&client, "UC123", "KEY").unwrap_err();
+        assert!(matches!(error, FeedError::BadResponse(_)));
+    }
+
+    #[test]
+    fn comments_come_with_their_inlined_replies() {
+        let client = FixtureClient::new().with(
+            "commentThreads?",
+            &json!({
+                "items": [
+                    thread("t2", T + 20, 0, &[]),
+                    thread("t1", T + 10, 1, &[comment("r1", "replier", "hi", T + 15)]),
+                ]
+            }),
+        );
+        let (comments, reply_counts) =
+            get_comments_from_video(&client, "KEY", "vid", T, 10, &HashMap::new()).unwrap();
+
+        let ids: Vec<&str> = comments.iter().map(|comment| comment.id.as_str()).collect();
+        assert_eq!(ids, ["t2", "t1", "r1"]);
+        assert_eq!(comments[2].thread_id, "t1");
+        assert_eq!(comments[2].reply_to.as_deref(), Some("op"));
+        assert_eq!(comments[2].timestamp, T + 15);
+        assert_eq!(comments[2].author_channel_id.as_deref(), Some("UCreplier"));
+        assert_eq!(reply_counts.get("t1"), Some(&1));
+        assert_eq!(client.requests().len(), 1);
+    }
+
+    #[test]
+    fn comment_pages_stop_at_old_comments() {
+        let client = FixtureClient::new()
+            .with(
+                "pageToken=PAGE3",
+                &json!({ "items": [thread("t1", T - 100, 0, &[])] }),
+            )
+            .with(
+                "pageToken=PAGE2",
+                &json!({
+                    "nextPageToken": "PAGE3",
+                    "items": [thread("t3", T + 5, 0, &[]), thread("t2", T - 5, 0, &[])]
+                }),
+            )
+            .with(
+                "commentThreads?",
+                &json!({
+                    "nextPageToken": "PAGE2",
+                    "items": [thread("t4", T + 10, 0, &[])]
+                }),
+            );
+        let (comments, _) =
+            get_comments_from_video(&client, "KEY", "vid", T, 10, &HashMap::new()).unwrap();
+
+        // The page with the old comment is kept whole, but the one after it never gets asked for.
+        let ids: Vec<&str> = comments.iter().map(|comment| comment.id.as_str()).collect();
+        assert_eq!(ids, ["t4", "t3", "t2"]);
+        assert_eq!(client.requests().len(), 2);
+    }
+
+    #[test]
+    fn comment_pages_stop_at_max_pages() {
+        let client = FixtureClient::new().with(
+            "commentThreads?",
+            &json!({ "nextPageToken": "MORE", "items": [thread("t1", T + 10, 0, &[])] }),
+        );
+        listen();
+        get_comments_from_video(&client, "KEY", "capped", T, 3, &HashMap::new()).unwrap();
+        assert_eq!(client.requests().len(), 3);
+        // Whatever was on the pages after that is lost, so say so.
+        assert!(heard().contains(&Notice::PagesRanOut {
+            video_id: "capped".to_string(),
+            max_pages: 3
+        }));
+    }
+
+    #[test]
+    fn busy_threads_get_their_replies_pulled() {
+        let client = FixtureClient::new()
+            .with(
+                "comments?",
+                &json!({
+                    "items": [
+                        comment("r1", "a", "one", T + 11),
+                        comment("r2", "b", "two", T + 12),
+                        comment("r3", "c", "three", T + 13),
+                    ]
+                }),
+            )
+            .with(
+                "commentThreads?",
+                &json!({
+                    "items": [thread("t1", T - 50, 3, &[comment("r3", "c", "three", T + 13)])]
+                }),
+            );
+
+        // Reply count went up since last time, so the whole list gets grabbed.
+        let known: HashMap<String, u64> = HashMap::from([("t1".to_string(), 1)]);
+        let (comments, reply_counts) =
+            get_comments_from_video(&client, "KEY", "vid", T, 10, &known).unwrap();
+        assert_eq!(comments.len(), 4);
+        assert_eq!(reply_counts.get("t1"), Some(&3));
+        assert!(client.requests()[1].contains("parentId=t1"));
+
+        // Same count as last time, nothing new to pull.
+        let client = FixtureClient::new().with(
+            "commentThreads?",
+            &json!({
+                "items": [thread("t1", T - 50, 3, &[comment("r3", "c", "three", T + 13)])]
+            }),
+        );
+        let known: HashMap<String, u64> = HashMap::from([("t1".to_string(), 3)]);
+        let (comments, _) = get_comments_from_video(&client, "KEY", "vid", T, 10, &known).unwrap();
+        assert_eq!(comments.len(), 2);
+        assert_eq!(client.requests().len(), 1);
+    }
+
+    #[test]
+    fn videos_without_comments_are_empty_not_errors() {
+        let client = FixtureClient::new().with("commentThreads?", &json!({ "items": [] }));
+        let (comments, reply_counts) =
+            get_comments_from_video(&client, "KEY", "vid", T, 10, &HashMap::new()).unwrap();
+        assert!(comments.is_empty());
+        assert!(reply_counts.is_empty());
+    }
+
+    #[test]
+    fn bad_comment_timestamps_are_bad_responses() {
+        let mut broken = thread("t1", T, 0, &[]);
+        broken["snippet"]["topLevelComment"]["snippet"]["publishedAt"] = json!("yesterday");
+        let client = FixtureClient::new().with("commentThreads?", &json!({ "items": [broken] }));
+        let error =
+            get_comments_from_video(&client, "KEY", "vid", T, 10, &HashMap::new()).unwrap_err();
+        assert!(matches!(error, FeedError::BadResponse(_)));
+    }
+
+    #[test]
+    fn channel_videos_are_paged_and_sorted_newest_first() {
+        let item = |id: &str, published: Option<u64>| {
+            json!({
+                "snippet": { "title": format!("Video {id}") },
+                "contentDetails": {
+                    "videoId": id,
+                    "videoPublishedAt": published.map(rfc3339)
+                }
+            })
+        };
+        let client = FixtureClient::new()
+            .with(
+                "channels?part=contentDetails",
+                &json!({
+                    "items": [{ "contentDetails": { "relatedPlaylists": { "uploads": "UU123" } } }]
+                }),
+            )
+            .with(
+                "pageToken=NEXT",
+                &json!({ "items": [item("c", Some(T + 30)), item("private", None)] }),
+            )
+            .with(
+                "playlistItems?",
+                &json!({
+                    "nextPageToken": "NEXT",
+                    "items": [item("a", Some(T)), item("b", Some(T + 10))]
+                }),
+            );
+        let videos = get_videos_from_channel(&client, "KEY", "UC123")
+            .unwrap()
+            .unwrap();
+
+        let ids: Vec<&str> = videos.iter().map(|video| video.id.as_str()).collect();
+        assert_eq!(ids, ["c", "b", "a"]);
+        assert_eq!(videos[0].title, "Video c");
+        assert_eq!(videos[0].published, T + 30);
+        assert!(client.requests()[1].contains("playlistId=UU123"));
+    }
+
+    #[test]
+    fn channels_without_uploads_have_no_videos() {
+        let client = FixtureClient::new().with("channels?", &json!({ "items": [] }));
+        assert!(get_videos_from_channel(&client, "KEY", "UC123")
+            .unwrap()
+            .unwrap()
+            .is_empty());
+
+        let client = FixtureClient::new()
+            .with(
+                "channels?",
+                &json!({
+                    "items": [{ "contentDetails": { "relatedPlaylists": { "uploads": "UU123" } } }]
+                }),
+            )
+            .with("playlistItems?", &api_error(404, "playlistNotFound"));
+        assert!(get_videos_from_channel(&client, "KEY", "UC123")
+            .unwrap()
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn unchanged_first_pages_mean_nothing_new() {
+        let client = FixtureClient::new()
+            .with(
+                "channels?part=contentDetails",
+                &json!({
+                    "items": [{ "contentDetails": { "relatedPlaylists": { "uploads": "UU123" } } }]
+                }),
+            )
+            .not_modified("playlistItems?")
+            .not_modified("commentThreads?");
+
+        assert!(get_videos_from_channel(&client, "KEY", "UC123")
+            .unwrap()
+            .is_none());
+
+        let known: HashMap<String, u64> = HashMap::from([("t1".to_string(), 3)]);
+        let (comments, reply_counts) =
+            get_comments_from_video(&client, "KEY", "vid", T, 10, &known).unwrap();
+        assert!(comments.is_empty());
+        assert_eq!(reply_counts, known);
+    }
+
+    #[test]
+    fn failed_checks_forget_the_first_page() {
+        let client = FixtureClient::new().failing("pageToken=NEXT").with(
+            "commentThreads?",
+            &json!({ "nextPageToken": "NEXT", "items": [thread("t1", T + 10, 0, &[])] }),
+        );
+        get_comments_from_video(&client, "KEY", "vid", T, 10, &HashMap::new()).unwrap_err();
+        assert_eq!(
+            client.forgotten(),
+            [comment_threads_url("KEY", "vid", None)]
+        );
+    }
+}
@@ -0,0 +1,167 @@
+// Canned youtube responses for the tests, and a client that serves them.
+
+use chrono::TimeZone;
+use serde_json::{json, Value};
+
+use crate::error::FeedError;
+use crate::http::HttpClient;
+use crate::notice::{self, Notice};
+
+// 2023-11-14T22:13:20Z
+pub const T: u64 = 1_700_000_000;
+
+pub fn rfc3339(timestamp: u64) -> String {
+    let Ok(seconds) = i64::try_from(timestamp) else {
+        panic!("timestamp too big");
+    };
+    chrono::Utc
+        .timestamp_opt(seconds, 0)
+        .single()
+        .map(|time| time.to_rfc3339())
+        .unwrap_or_default()
+}
+
+pub fn comment(id: &str, author: &str, text: &str, timestamp: u64) -> Value {
+    json!({
+        "id": id,
+        "snippet": {
+            "textOriginal": text,
+            "authorDisplayName": author,
+            "authorChannelId": { "value": format!("UC{author}") },
+            "likeCount": 3,
+            "publishedAt": rfc3339(timestamp)
+        }
+    })
+}
+
+pub fn thread(id: &str, timestamp: u64, total_replies: u64, replies: &[Value]) -> Value {
+    json!({
+        "id": id,
+        "snippet": {
+            "totalReplyCount": total_replies,
+            "topLevelComment": comment(id, "op", &format!("comment {id}"), timestamp)
+        },
+        "replies": { "comments": replies }
+    })
+}
+
+// Every notice sent since `listen` was first called, by any test. Tests run side by side,
+// so only look for your own.
+static HEARD: std::sync::Mutex<Vec<Notice>> = std::sync::Mutex::new(Vec::new());
+
+pub fn listen() {
+    static LISTENING: std::sync::Once = std::sync::Once::new();
+    LISTENING.call_once(|| {
+        notice::set_handler(|notice| {
+            HEARD
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner)
+                .push(notice.clone());
+        });
+    });
+}
+
+pub fn heard() -> Vec<Notice> {
+    HEARD
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+        .clone()
+}
+
+pub fn api_error(code: i64, reason: &str) -> Value {
+    json!({
+        "error": {
+            "code": code,
+            "message": format!("{reason} happened"),
+            "errors": [{ "reason": reason }]
+        }
+    })
+}
+
+// Stands in for youtube. Each request gets the body of the first fixture whose
+// pattern shows up in the URL, so more specific patterns need to go first.
+// A body of None is a 304, for when nothing changed.
+pub struct FixtureClient {
+    fixtures: Vec<(String, Result<Option<String>, String>)>,
+    requests: std::sync::Mutex<Vec<String>>, // Every URL asked for, in order.
+    forgotten: std::sync::Mutex<Vec<String>>, // Every URL passed to forget, in order.
+}
+
+impl FixtureClient {
+    pub fn new() -> Self {
+        Self {
+            fixtures: Vec::new(),
+            requests: std::sync::Mutex::new(Vec::new()),
+            forgotten: std::sync::Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn with(mut self, pattern: &str, body: &serde_json::Value) -> Self {
+        self.fixtures
+            .push((pattern.to_string(), Ok(Some(body.to_string()))));
+        self
+    }
+
+    pub fn with_raw(mut self, pattern: &str, body: &str) -> Self {
+        self.fixtures
+            .push((pattern.to_string(), Ok(Some(body.to_string()))));
+        self
+    }
+
+    pub fn failing(mut self, pattern: &str) -> Self {
+        // Requests matching this never get an answer.
+        self.fixtures
+            .push((pattern.to_string(), Err("connection refused".to_string())));
+        self
+    }
+
+    pub fn not_modified(mut self, pattern: &str) -> Self {
+        // Conditional requests matching this come back as a 304.
+        self.fixtures.push((pattern.to_string(), Ok(None)));
+        self
+    }
+
+    pub fn forgotten(&self) -> Vec<String> {
+        self.forgotten
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .clone()
+    }
+
+    pub fn requests(&self) -> Vec<String> {
+        self.requests
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .clone()
+    }
+}
+
+impl HttpClient for FixtureClient {
+    fn get(&self, url: &str) -> Result<String, FeedError> {
+        self.get_if_changed(url)?
+            .ok_or_else(|| FeedError::BadResponse(format!("304 for {url}")))
+    }
+
+    fn get_if_changed(&self, url: &str) -> Result<Option<String>, FeedError> {
+        self.requests
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .push(url.to_string());
+        match self
+            .fixtures
+            .iter()
+            .find(|(pattern, _)| url.contains(pattern.as_str()))
+        {
+            Some((_, Ok(body))) => Ok(body.clone()),
+            Some((_, Err(error))) => Err(FeedError::Transport(error.clone())),
+            None => Err(FeedError::Transport(format!("No fixture for {url}"))),
+        }
+    }
+
+    fn forget(&self, url: &str) {
+        self.forgotten
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .push(url.to_string());
+    }
+}
@@ -0,0 +1,527 @@
+//! Everything that goes over the network goes through an [`HttpClient`], so the fetching
+//! and parsing code can be fed canned responses instead of talking to youtube.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use curl::easy::{Easy, List};
+use serde_json::Value;
+
+use crate::error::{ApiReason, FeedError};
+use crate::notice::{self, Notice};
+use crate::quota;
+use crate::redact::{self, redact};
+
+/// Something that can send GET requests.
+pub trait HttpClient: Send + Sync {
+    /// Runs a GET and hands back the body.
+    ///
+    /// # Errors
+    ///
+    /// [`FeedError::Transport`] if there was no answer at all. API errors come back as a body
+    /// like any other.
+    fn get(&self, url: &str) -> Result<String, FeedError>;
+
+    /// Like [`get`](HttpClient::get), but only hands back the body if it changed since the
+    /// last time this URL was asked for this way. `None` means nothing changed.
+    ///
+    /// Clients that don't keep track of that can leave this be, everything counts as changed.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`get`](HttpClient::get).
+    fn get_if_changed(&self, url: &str) -> Result<Option<String>, FeedError> {
+        self.get(url).map(Some)
+    }
+
+    /// Forgets what came back for a URL last time, so the next
+    /// [`get_if_changed`](HttpClient::get_if_changed) counts it as changed. For when that
+    /// response ended up getting thrown away.
+    fn forget(&self, _url: &str) {}
+}
+
+/// Sends requests with curl, retrying network trouble and server errors.
+///
+/// Curl handles are kept around between requests, so connections to google stay open instead
+/// of being set up again for every call. Responses are gzipped on the way over.
+///
+/// [`get_if_changed`](HttpClient::get_if_changed) remembers the `ETag` youtube sent with each
+/// URL and sends it back with `If-None-Match`, so unchanged responses come back as an empty 304.
+pub struct CurlClient {
+    handles: Mutex<Vec<Easy>>, // Idle handles, ready for the next request.
+    etags: Mutex<HashMap<String, String>>, // URL -> ETag of the last response to it.
+    /// Send a [`Notice::Request`] with how long each request took.
+    pub verbose: bool,
+}
+
+impl CurlClient {
+    /// A client with no connections open yet.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            handles: Mutex::new(Vec::new()),
+            etags: Mutex::new(HashMap::new()),
+            verbose: false,
+        }
+    }
+
+    fn c_get(&self, input: &str, conditional: bool) -> Result<Option<String>, FeedError> {
+        // Runs a GET, retrying network failures and server errors (5xx) with jittered
+        // exponential backoff. Everything else is handed back for the caller to deal with.
+        // Conditional requests send the last ETag for this URL, and give back None on a 304.
+
+        let etag: Option<String> = if conditional {
+            self.etags
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .get(input)
+                .cloned()
+        } else {
+            None
+        };
+
+        let mut attempt: u32 = 0;
+        loop {
+            attempt += 1;
+            let started: Instant = Instant::now();
+            let result: Result<Response, String> = self.c_get_once(input, etag.as_deref());
+
+            if self.verbose {
+                // Never the whole URL, the key is in there.
+                let outcome: String = result.as_ref().map_or_else(
+                    |_| "failed".to_string(),
+                    |response| response.code.to_string(),
+                );
+                notice::send(&Notice::Request {
+                    endpoint: quota::endpoint_of(input).to_string(),
+                    outcome,
+                    millis: started.elapsed().as_millis(),
+                });
+            }
+
+            let failure: String = match result {
+                // Same as last time.
+                Ok(response) if response.code == 304 && etag.is_some() => return Ok(None),
+                Ok(response) if response.code < 500 => {
+                    // Only remember good responses, errors are worth asking about again.
+                    if let (true, 200, Some(new_etag)) = (conditional, response.code, response.etag)
+                    {
+                        self.etags
+                            .lock()
+                            .unwrap_or_else(PoisonError::into_inner)
+                            .insert(input.to_string(), new_etag);
+                    }
+                    return Ok(Some(response.body));
+                }
+                Ok(response) => format!("Server error {}", response.code),
+                Err(e) => e,
+            };
+
+            // Curl's errors can have the URL in them, and the URL has the key in it.
+            let failure: String = redact(&failure);
+
+            if attempt >= MAX_ATTEMPTS {
+                return Err(FeedError::Transport(format!(
+                    "{failure} (gave up after {attempt} tries)"
+                )));
+            }
+
+            // 1, 2, 4, 8 seconds, plus up to that much again at random so retries don't bunch up.
+            let backoff: u64 = 1000 << (attempt - 1);
+            let wait: u64 = backoff + fastrand::u64(0..=backoff);
+            notice::send(&Notice::Retrying {
+                failure,
+                wait_ms: wait,
+            });
+            std::thread::sleep(Duration::from_millis(wait));
+        }
+    }
+
+    fn c_get_once(&self, input: &str, etag: Option<&str>) -> Result<Response, String> {
+        // A single GET, sending `etag` along in If-None-Match if there is one.
+
+        // Grab an idle handle, or make one if they're all busy.
+        let idle: Option<Easy> = self
+            .handles
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .pop();
+        let mut curl: Easy = match idle {
+            Some(curl) => curl,
+            None => new_handle()?,
+        };
+
+        // Set the URL
+        match curl.url(input) {
+            Ok(()) => (),
+            Err(e) => return Err(format!("Bad URL! : {e}")),
+        }
+
+        // Headers go on every request, since the ETag changes from one to the next.
+        let mut headers = List::new();
+        let mut header_lines: Vec<String> = vec!["Accept: application/json".to_string()];
+        if let Some(etag) = etag {
+            header_lines.push(format!("If-None-Match: {etag}"));
+        }
+        for line in header_lines {
+            match headers.append(&line) {
+                Ok(()) => (),
+                Err(e) => return Err(format!("Couldn't set up the request! : {e}")),
+            }
+        }
+        match curl.http_headers(headers) {
+            Ok(()) => (),
+            Err(e) => return Err(format!("Couldn't set up the request! : {e}")),
+        }
+
+        // Every request costs quota, even ones that fail.
+        quota::record(input);
+
+        let mut data: Vec<u8> = Vec::new();
+        let mut response_etag: Option<String> = None;
+        let performed: Result<(), curl::Error> = {
+            let mut transfer = curl.transfer();
+            let setup: Result<(), curl::Error> = transfer
+                .write_function(|response_data: &[u8]| {
+                    data.extend_from_slice(response_data);
+                    Ok(response_data.len())
+                })
+                .and_then(|()| {
+                    transfer.header_function(|header: &[u8]| {
+                        // Header lines come through one at a time, "Name: value\r\n".
+                        let line: String = String::from_utf8_lossy(header).to_string();
+                        if let Some((name, value)) = line.split_once(':') {
+                            if name.trim().eq_ignore_ascii_case("etag") {
+                                response_etag = Some(value.trim().to_string());
+                            }
+                        }
+                        true
+                    })
+                });
+            match setup {
+                Ok(()) => transfer.perform(),
+                Err(e) => Err(e),
+            }
+        };
+        match performed {
+            Ok(()) => (),
+            // Don't put it back, a fresh handle is a better bet than whatever state this one is in.
+            Err(e) => return Err(e.to_string()),
+        }
+
+        let code: u32 = match curl.response_code() {
+            Ok(okay) => okay,
+            Err(e) => return Err(e.to_string()),
+        };
+
+        // Done with it, the next request can have it (and its open connection).
+        self.handles
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(curl);
+
+        // Convert the Vec<u8> to a String.
+        Ok(Response {
+            code,
+            body: String::from_utf8_lossy(&data).to_string(),
+            etag: response_etag,
+        })
+    }
+}
+
+// What came back from a single request.
+struct Response {
+    code: u32,
+    body: String,
+    etag: Option<String>,
+}
+
+impl Default for CurlClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HttpClient for CurlClient {
+    fn get(&self, url: &str) -> Result<String, FeedError> {
+        // Nothing to compare against, so this always gets a body.
+        let Some(body) = self.c_get(url, false)? else {
+            return Err(FeedError::BadResponse(
+                "Got a 304 without asking for one!".to_string(),
+            ));
+        };
+        Ok(body)
+    }
+
+    fn get_if_changed(&self, url: &str) -> Result<Option<String>, FeedError> {
+        self.c_get(url, true)
+    }
+
+    fn forget(&self, url: &str) {
+        self.etags
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(url);
+    }
+}
+
+/// Spreads requests over more than one API key, for when one key's daily quota isn't enough.
+///
+/// Whatever key a URL comes with gets swapped out for the key in use. That key sticks until
+/// google says it's out of quota, going too fast, or not valid, or it's used up its share of the
+/// [budget](quota::set_budget), then the next key takes over and the request is sent again.
+/// Once every key is out, the error comes back as usual. That's a quota error as long as any
+/// key is only out for now, so a bad key can't make it look like there's no point carrying on.
+pub struct KeyRotation {
+    inner: Box<dyn HttpClient>,
+    keys: Vec<String>,
+    current: AtomicUsize, // Index of the key in use.
+}
+
+impl KeyRotation {
+    /// Rotates through `keys`, in order, sending requests with `inner`.
+    ///
+    /// The keys are [tracked separately](quota::add_key) and [hidden](redact::hide) from
+    /// error messages.
+    #[must_use]
+    pub fn new(inner: Box<dyn HttpClient>, keys: Vec<String>) -> Self {
+        for key in &keys {
+            quota::add_key(key);
+            redact::hide(key);
+        }
+        Self {
+            inner,
+            keys,
+            current: AtomicUsize::new(0),
+        }
+    }
+
+    fn pick(&self) -> Option<usize> {
+        // The key in use if it's still good, otherwise the next one that is.
+        let start: usize = self.current.load(Ordering::Relaxed);
+        let index: usize = (0..self.keys.len())
+            .map(|offset| (start + offset) % self.keys.len())
+            .find(|&index| quota::key_usable(&self.keys[index]))?;
+        if index != start {
+            notice::send(&Notice::SwitchedKey(index + 1));
+            self.current.store(index, Ordering::Relaxed);
+        }
+        Some(index)
+    }
+
+    fn send<T>(
+        &self,
+        url: &str,
+        request: impl Fn(&str) -> Result<T, FeedError>,
+        body_of: impl Fn(&T) -> Option<&str>,
+    ) -> Result<T, FeedError> {
+        // Every key gets one go at most, so this can't spin forever.
+        let mut out_of_quota: Option<T> = None;
+        let mut invalid: Option<T> = None;
+        for _ in 0..self.keys.len() {
+            let Some(index) = self.pick() else {
+                break;
+            };
+            let key: &str = &self.keys[index];
+            let response: T = request(&with_key(url, key))?;
+
+            // Only quota and key trouble are worth another key, everything else is handed back.
+            let reason: Option<ApiReason> = body_of(&response)
+                .and_then(|body| serde_json::from_str::<Value>(body).ok())
+                .and_then(|json| FeedError::from_response(&json))
+                .and_then(|error| error.reason().cloned());
+            match reason {
+                Some(ApiReason::QuotaExceeded) => quota::mark_key_exhausted(key),
+                Some(ApiReason::RateLimited) => quota::mark_key_rate_limited(key),
+                Some(ApiReason::KeyInvalid) => {
+                    quota::mark_key_invalid(key);
+                    invalid = Some(response);
+                    continue;
+                }
+                _ => return Ok(response),
+            }
+            out_of_quota = Some(response);
+        }
+
+        // Out of keys. Keys that are only out for now come back later, so that's the error
+        // to hand back, whether or not they got a go this time.
+        if let Some(response) = out_of_quota {
+            return Ok(response);
+        }
+        if self.keys.iter().any(|key| !quota::key_invalid(key)) {
+            return Err(FeedError::Api {
+                status: 403,
+                reason: ApiReason::QuotaExceeded,
+                message: "Every working API key is out of quota for now!".to_string(),
+            });
+        }
+        if let Some(response) = invalid {
+            return Ok(response);
+        }
+        let key: &str = self
+            .keys
+            .get(self.current.load(Ordering::Relaxed))
+            .map_or("", String::as_str);
+        request(&with_key(url, key))
+    }
+
+    fn variants(&self, url: &str) -> Vec<String> {
+        // The URL with each of the keys in it.
+        self.keys.iter().map(|key| with_key(url, key)).collect()
+    }
+}
+
+impl HttpClient for KeyRotation {
+    fn get(&self, url: &str) -> Result<String, FeedError> {
+        self.send(url, |url| self.inner.get(url), |body| Some(body.as_str()))
+    }
+
+    fn get_if_changed(&self, url: &str) -> Result<Option<String>, FeedError> {
+        self.send(url, |url| self.inner.get_if_changed(url), Option::as_deref)
+    }
+
+    fn forget(&self, url: &str) {
+        // Whichever key it went out with.
+        for variant in self.variants(url) {
+            self.inner.forget(&variant);
+        }
+    }
+}
+
+fn with_key(url: &str, key: &str) -> String {
+    // Swaps the `key` parameter of a URL for `key`, or adds one if there isn't any.
+    let Some((base, query)) = url.split_once('?') else {
+        return format!("{url}?key={key}");
+    };
+    let mut found: bool = false;
+    let mut params: Vec<String> = query
+        .split('&')
+        .map(|param| {
+            if param.starts_with("key=") {
+                found = true;
+                format!("key={key}")
+            } else {
+                param.to_string()
+            }
+        })
+        .collect();
+    if !found {
+        params.push(format!("key={key}"));
+    }
+    format!("{base}?{}", params.join("&"))
+}
+
+// How many times to try a request before giving up on it.
+const MAX_ATTEMPTS: u32 = 5;
+
+fn new_handle() -> Result<Easy, String> {
+    // Sets up a curl handle with everything that stays the same between requests.
+    let mut curl = Easy::new();
+
+    // Ask for gzip (or whatever else curl can unpack), JSON squashes down a lot.
+    match curl.accept_encoding("") {
+        Ok(()) => (),
+        Err(e) => return Err(format!("Couldn't set up the request! : {e}")),
+    }
+
+    // Keep idle connections from getting dropped between polls.
+    match curl.tcp_keepalive(true) {
+        Ok(()) => (),
+        Err(e) => return Err(format!("Couldn't set up the request! : {e}")),
+    }
+
+    // Don't let a dead connection hang us forever.
+    match curl.timeout(Duration::from_secs(30)) {
+        Ok(()) => (),
+        Err(e) => return Err(format!("Couldn't set up the request! : {e}")),
+    }
+
+    Ok(curl)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{api_error, FixtureClient};
+    use serde_json::json;
+
+    #[test]
+    fn keys_are_swapped_into_urls() {
+        assert_eq!(
+            with_key("https://example.com/comments?key=OLD&part=snippet", "NEW"),
+            "https://example.com/comments?key=NEW&part=snippet"
+        );
+        assert_eq!(
+            with_key("https://example.com/comments?part=snippet", "NEW"),
+            "https://example.com/comments?part=snippet&key=NEW"
+        );
+    }
+
+    #[test]
+    fn spent_and_broken_keys_are_skipped() {
+        // Keys nothing else uses, the quota ledger is shared between tests.
+        let keys: Vec<String> = ["ROTATE_SPENT", "ROTATE_BROKEN", "ROTATE_GOOD"]
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        let client = FixtureClient::new()
+            .with("key=ROTATE_SPENT", &api_error(403, "quotaExceeded"))
+            .with("key=ROTATE_BROKEN", &api_error(400, "keyInvalid"))
+            .with("key=ROTATE_GOOD", &json!({ "items": [] }));
+        let rotation = KeyRotation::new(Box::new(client), keys);
+
+        let body = rotation
+            .get("https://example.com/comments?key=ROTATE_SPENT")
+            .unwrap();
+        assert_eq!(body, json!({ "items": [] }).to_string());
+        assert!(!quota::key_usable("ROTATE_SPENT"));
+        assert!(!quota::key_usable("ROTATE_BROKEN"));
+
+        // And it sticks with the one that worked.
+        rotation
+            .get("https://example.com/comments?key=ROTATE_SPENT")
+            .unwrap();
+        assert_eq!(rotation.current.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn rate_limited_keys_only_stop_themselves() {
+        let keys: Vec<String> = ["LIMITED_BUSY", "LIMITED_SPARE"]
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        let client = FixtureClient::new()
+            .with("key=LIMITED_BUSY", &api_error(403, "rateLimitExceeded"))
+            .with("key=LIMITED_SPARE", &json!({ "items": [] }));
+        let rotation = KeyRotation::new(Box::new(client), keys);
+
+        let body = rotation.get("https://example.com/comments").unwrap();
+        assert_eq!(body, json!({ "items": [] }).to_string());
+        // Only for a minute, it isn't out for the day.
+        assert!(!quota::key_usable("LIMITED_BUSY"));
+        assert!(quota::key_usable("LIMITED_SPARE"));
+    }
+
+    #[test]
+    fn spent_keys_outlast_broken_ones() {
+        let keys: Vec<String> = ["OUTLAST_SPENT", "OUTLAST_BROKEN"]
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        let client = FixtureClient::new()
+            .with("key=OUTLAST_SPENT", &api_error(403, "quotaExceeded"))
+            .with("key=OUTLAST_BROKEN", &api_error(400, "keyInvalid"));
+        let rotation = KeyRotation::new(Box::new(client), keys);
+
+        // The spent key comes back tomorrow, so it's worth waiting for rather than giving up.
+        let body = rotation.get("https://example.com/comments").unwrap();
+        let error = FeedError::from_response(&serde_json::from_str(&body).unwrap()).unwrap();
+        assert_eq!(error.reason(), Some(&ApiReason::QuotaExceeded));
+
+        // Same again once neither key is worth trying.
+        let error = rotation.get("https://example.com/comments").unwrap_err();
+        assert!(error.is_quota());
+    }
+}
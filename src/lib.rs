@@ -0,0 +1,264 @@
+// DocJade 2023
+
+//! Watch a youtube channel for new comments.
+//!
+//! [`Feed`] is the easy way in. Point it at a channel and loop over it:
+//!
+//! ```no_run
+//! use youtube_comment_feed::Feed;
+//!
+//! for event in Feed::new("API_KEY", "CHANNEL_ID") {
+//!     match event {
+//!         Ok(event) => println!("{}: {}", event.comment.author_name, event.comment.content),
+//!         Err(error) => eprintln!("{error}"),
+//!     }
+//! }
+//! ```
+//!
+//! With the `stream` feature, `Feed::stream` does the same as an async stream.
+//!
+//! For more control, call [`Feed::refresh_videos`] and [`Feed::poll`] yourself and pick new
+//! comments out of each video's [`queued_comments`](TrackedVideo::queued_comments). The
+//! functions those are built out of are here too, for doing things differently.
+//!
+//! Nothing gets printed. Retries, deleted videos and the like are [`Notice`]s, which go to
+//! [`notice::set_handler`] if you want them. The handler, the [`quota`] ledger and the
+//! [hidden secrets](redact) are shared by every feed in the process.
+
+// Make Clippy angry
+#![warn(
+    clippy::pedantic,
+    clippy::nursery,
+    clippy::unwrap_used,
+    clippy::expect_used,
+    clippy::correctness,
+    clippy::style,
+    clippy::perf,
+    clippy::complexity,
+    clippy::cognitive_complexity,
+    clippy::double_parens,
+    clippy::len_zero,
+    clippy::question_mark,
+    clippy::suspicious,
+    clippy::todo,
+    //clippy::all  //for extra anger
+)]
+
+pub mod archive;
+mod channel;
+pub mod error;
+mod events;
+mod fetch;
+#[cfg(test)]
+mod fixtures;
+pub mod http;
+pub mod notice;
+pub mod quota;
+pub mod redact;
+pub mod state;
+mod tracking;
+
+pub use channel::{playlist_id_of, video_id_of, ChannelInfo, ChannelRef};
+pub use error::{ApiReason, FeedError};
+#[cfg(feature = "stream")]
+pub use events::CommentStream;
+pub use events::{CommentEvent, Comments};
+pub use fetch::{
+    get_comments_from_video, get_replies_from_thread, get_uploads_playlist, get_videos_by_id,
+    get_videos_from_channel, get_videos_from_playlist, test_channel_and_key, test_playlist_and_key,
+    Video, YTComment,
+};
+pub use http::{CurlClient, HttpClient, KeyRotation};
+pub use notice::Notice;
+pub use tracking::{
+    queue_comments, update_video_list, update_video_list_from_ids, update_video_list_from_playlist,
+    FetchLimits, PollSchedule, TrackedVideo, VideoSource, DISABLED_RECHECK_DELAY,
+    REPLY_COUNT_LIMIT, SEEN_ID_LIMIT,
+};
+
+// Store this bit of the youtube url to save space
+const API_URL: &str = "https://youtube.googleapis.com/youtube/v3/";
+
+/// The current unix timestamp in seconds.
+#[must_use]
+pub fn now() -> u64 {
+    chrono::Utc::now()
+        .timestamp()
+        .try_into()
+        .unwrap_or_default()
+}
+
+/// Watches every video on one channel (or in one playlist, or a few picked out videos) for new
+/// comments.
+pub struct Feed {
+    /// Where requests go. A [`CurlClient`] unless you need something else.
+    pub client: Box<dyn HttpClient>,
+    pub api_key: String,
+    pub channel_id: String,
+    /// What to watch. The whole channel, unless this says otherwise.
+    pub source: VideoSource,
+    pub schedule: PollSchedule,
+    pub limits: FetchLimits,
+    /// How many seconds to wait between checking the channel for new uploads, when iterating.
+    pub list_refresh: u64,
+    /// Every video being watched. Fill this in to pick up from a saved state.
+    pub videos: Vec<TrackedVideo>,
+}
+
+impl Feed {
+    /// A feed for a channel, with the default schedule and no videos yet.
+    ///
+    /// The key gets [hidden](redact::hide) from error messages from here on.
+    #[must_use]
+    pub fn new(api_key: &str, channel_id: &str) -> Self {
+        redact::hide(api_key);
+        Self {
+            client: Box::new(CurlClient::new()),
+            api_key: api_key.to_string(),
+            channel_id: channel_id.to_string(),
+            source: VideoSource::Channel,
+            schedule: PollSchedule::default(),
+            limits: FetchLimits::default(),
+            list_refresh: 600,
+            videos: Vec::new(),
+        }
+    }
+
+    /// Checks that the API key works and that whatever's being watched exists, and returns
+    /// its name: the channel's, the playlist's, or for [`VideoSource::Videos`], the channel
+    /// they're on.
+    ///
+    /// If `channel_id` is a handle or URL, it gets swapped out for the real ID, so call this
+    /// before anything else.
+    ///
+    /// # Errors
+    ///
+    /// See [`test_channel_and_key`] and [`test_playlist_and_key`].
+    /// [`FeedError::VideoNotFound`] if one of the videos isn't there.
+    pub fn check(&mut self) -> Result<String, FeedError> {
+        match &self.source {
+            VideoSource::Channel => {
+                let channel: ChannelInfo =
+                    test_channel_and_key(&*self.client, &self.channel_id, &self.api_key)?;
+                self.channel_id = channel.id;
+                Ok(channel.name)
+            }
+            VideoSource::Playlist(playlist_id) => {
+                test_playlist_and_key(&*self.client, playlist_id, &self.api_key)
+            }
+            VideoSource::Videos(video_ids) => {
+                let videos: Vec<Video> = get_videos_by_id(&*self.client, &self.api_key, video_ids)?;
+                if let Some(missing) = video_ids
+                    .iter()
+                    .find(|id| !videos.iter().any(|video| &video.id == *id))
+                {
+                    return Err(FeedError::VideoNotFound(missing.clone()));
+                }
+                // Usually they're all off of the one channel, like a premiere and its trailer.
+                let channel_ids: Vec<&str> = videos
+                    .iter()
+                    .map(|video| video.channel_id.as_str())
+                    .collect();
+                match channel_ids.first() {
+                    Some(first) if channel_ids.iter().all(|id| id == first) => {
+                        test_channel_and_key(&*self.client, first, &self.api_key)
+                            .map(|channel| channel.name)
+                    }
+                    _ => Ok("Several channels".to_string()),
+                }
+            }
+        }
+    }
+
+    /// Lists every public video the feed would watch, newest first.
+    ///
+    /// # Errors
+    ///
+    /// Any failed request.
+    pub fn list_videos(&self) -> Result<Vec<Video>, FeedError> {
+        match &self.source {
+            VideoSource::Channel => {
+                // Not get_videos_from_channel, this shouldn't use up the "has it changed"
+                // check that refresh_videos relies on.
+                let Some(playlist_id) =
+                    get_uploads_playlist(&*self.client, &self.api_key, &self.channel_id)?
+                else {
+                    return Ok(Vec::new());
+                };
+                get_videos_from_playlist(&*self.client, &self.api_key, &playlist_id)
+            }
+            VideoSource::Playlist(playlist_id) => {
+                get_videos_from_playlist(&*self.client, &self.api_key, playlist_id)
+            }
+            VideoSource::Videos(video_ids) => {
+                let mut videos: Vec<Video> =
+                    get_videos_by_id(&*self.client, &self.api_key, video_ids)?;
+                videos.sort_by_key(|video| std::cmp::Reverse(video.published));
+                Ok(videos)
+            }
+        }
+    }
+
+    /// Starts watching any new videos. Their comments only count as new if they're newer
+    /// than `start_timestamp`.
+    ///
+    /// # Errors
+    ///
+    /// Any failed request, in which case the videos are left as they were.
+    pub fn refresh_videos(&mut self, start_timestamp: u64) -> Result<(), FeedError> {
+        let client: &dyn HttpClient = &*self.client;
+        let old: Vec<TrackedVideo> = self.videos.clone();
+        self.videos = match &self.source {
+            VideoSource::Channel => update_video_list(
+                client,
+                old,
+                &self.channel_id,
+                &self.api_key,
+                start_timestamp,
+                self.schedule,
+            ),
+            VideoSource::Playlist(playlist_id) => update_video_list_from_playlist(
+                client,
+                old,
+                playlist_id,
+                &self.api_key,
+                start_timestamp,
+                self.schedule,
+            ),
+            VideoSource::Videos(video_ids) => update_video_list_from_ids(
+                client,
+                old,
+                video_ids,
+                &self.api_key,
+                start_timestamp,
+                self.schedule,
+            ),
+        }?;
+        Ok(())
+    }
+
+    /// Whether any video is due for a check at `current_time`.
+    #[must_use]
+    pub fn is_due(&self, current_time: u64) -> bool {
+        self.videos
+            .iter()
+            .any(|video| video.next_check <= current_time)
+    }
+
+    /// Checks every video that's due, queueing up any new comments on them.
+    ///
+    /// # Errors
+    ///
+    /// See [`queue_comments`]. The videos are left as they were.
+    pub fn poll(&mut self, current_time: u64) -> Result<(), FeedError> {
+        self.videos = queue_comments(
+            &*self.client,
+            &self.videos,
+            &self.api_key,
+            current_time,
+            self.schedule,
+            self.limits,
+        )?;
+        Ok(())
+    }
+}
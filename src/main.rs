@@ -20,628 +20,630 @@
 )]
 
 // TODO: convert emoji's into their names.
-// TODO: Recurse into the comments to check their comments on the comments? // might be too expensive.
 
-use core::panic;
+// The command line side of things. Everything that talks to youtube lives in the library.
+
+use std::path::{Path, PathBuf};
+
+mod config;
+mod output;
+mod search;
+mod status;
 
 // Import the CLI argument parser
-use clap::Parser;
-// curl library
-use curl::easy::{Easy, List};
-// json handling
-use serde_json::Value;
-// time handling
-use chrono::DateTime;
+use clap::{Parser, Subcommand};
 // colored text
 use colored::Colorize;
 
+use youtube_comment_feed::{
+    archive, notice, now, quota, state, CurlClient, Feed, FetchLimits, HttpClient, KeyRotation,
+    Notice, PollSchedule, TrackedVideo, Video, VideoSource, YTComment,
+};
+
 // Set up command line arguments
+// Anything left out here can come from the config file instead, see config.rs.
 #[derive(Parser, Debug)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+    // TOML file with any of these settings in it.
+    // Defaults to $XDG_CONFIG_HOME/youtube_comment_feed/config.toml, if it's there.
+    #[arg(long)]
+    config: Option<PathBuf>,
     // Youtube API token
-    // Required.
-    #[arg(long, required = true)]
-    api_key: String,
-    #[arg(long, required = true)]
-    channel_id: String,
+    // Required, but this leaks into shell history and `ps`. Better to use $YOUTUBE_API_KEY
+    // (comma separated), --api-key-file, the config file, or the OS keyring (with the keyring
+    // feature). Give more than one to rotate through them when one runs out of quota.
+    #[arg(long)]
+    api_key: Vec<String>,
+    // File with API tokens in it, one per line.
+    #[arg(long)]
+    api_key_file: Option<PathBuf>,
+    // A UC... ID, an @handle, or a link to the channel or one of its videos.
+    // Give it more than once to watch several channels.
+    #[arg(long)]
+    channel_id: Vec<String>,
+    // Watch just this video, by ID or link. Give it more than once for more videos.
+    #[arg(long)]
+    video: Vec<String>,
+    // Watch the videos in a playlist (by ID or link), and anything added to it later.
+    #[arg(long)]
+    playlist: Option<String>,
+    // How many seconds to wait between checking the channel for new uploads. [default: 600]
+    #[arg(long)]
+    list_refresh: Option<u64>,
+    // Fastest a video will be polled, in seconds. Used for videos with fresh comments. [default: 10]
+    #[arg(long)]
+    min_delay: Option<u16>,
+    // Slowest a video will be polled, in seconds. Quiet videos back off up to this. [default: 3600]
+    #[arg(long)]
+    max_delay: Option<u16>,
+    // Most pages of 100 comments to grab from a video in one check. [default: 10]
+    #[arg(long)]
+    max_pages: Option<u16>,
+    // Most videos to check at the same time. [default: 4]
+    #[arg(long, value_parser = clap::value_parser!(u16).range(1..))]
+    parallel: Option<u16>,
+    // File to save tracking progress to, so restarts pick up where they left off.
+    #[arg(long)]
+    state: Option<PathBuf>,
+    // SQLite database to keep every comment in.
+    #[arg(long)]
+    archive: Option<PathBuf>,
+    // Daily API quota units to stay under, per key. Polling slows down, and eventually pauses,
+    // to fit. [default: 10000]
+    #[arg(long)]
+    quota_budget: Option<u64>,
+    // Print every request and how long it took.
+    #[arg(long)]
+    verbose: bool,
+    // How to print comments. [default: text]
+    // Only comments go to stdout, everything else goes to stderr, so json can be piped along.
+    #[arg(long, value_enum)]
+    format: Option<output::OutputFormat>,
+    // Only show comments matching this regex. Can be given more than once.
+    #[arg(long)]
+    include: Vec<String>,
+    // Hide comments matching this regex. Can be given more than once.
+    #[arg(long)]
+    exclude: Vec<String>,
+    // Hide comments from this author name or channel ID. Can be given more than once.
+    #[arg(long)]
+    ignore_author: Vec<String>,
+    // Run this through `sh -c` for every comment shown, with the comment as JSON on stdin.
+    // Can be given more than once.
+    #[arg(long)]
+    notify_command: Vec<String>,
+    // POST every comment shown to this URL, as JSON. Can be given more than once.
+    #[arg(long)]
+    webhook: Vec<String>,
 }
 
-// Store this bit of the youtube url to save space
-const API_URL: &str = "https://youtube.googleapis.com/youtube/v3/";
+// A channel (or playlist, or some videos) being watched, and what it's called so its
+// comments can be labelled.
+struct Channel {
+    name: String,
+    feed: Feed,
+    video_ids: Vec<String>, // What was there at startup, for handing saved videos back out.
+    listed_at: u64,         // When the video list was last fetched without trouble.
+}
 
-#[derive(Debug, Clone)]
-struct TrackedVideo {
-    title: String,
-    video_id: String,
-    most_recent_timestamp: u64, // The timestamp of the most recent comment we saw last update.
-    queued_comments: Vec<YTComment>, // Comments that are waiting for the print cycle.
-    recheck_delay: u16,         // How many seconds to wait until next update.
+#[derive(Subcommand, Debug)]
+enum Command {
+    // Search the comment archive instead of watching.
+    Search(search::SearchArgs),
+    // List the videos in a state file, and how each one is being watched.
+    Status(status::StatusArgs),
 }
 
 fn main() {
-    let args: Args = init();
-    let api_key: &str = &args.api_key;
-    let channel_id: &str = &args.channel_id;
-
-    // Now that everything is ready to go, lets start tracking
-    // comments!
+    let args: Args = Args::parse();
 
-    // The master Vec contains the TrackedVideo struct for... tracking videos.
-    let mut master: Vec<TrackedVideo> = Vec::new();
+    // Subcommands do their own thing.
+    if let Some(command) = args.command {
+        match command {
+            Command::Search(search_args) => search::run(search_args),
+            Command::Status(status_args) => status::run(&status_args),
+        }
+        return;
+    }
 
-    // Now we shall add all videos that currently exist on input channel
-    // and set the most recent timestamp to NOW
-    println!("Building tracked videos list...");
-    let returned = update_video_list(master.clone(), channel_id, api_key);
-    match returned {
-        Ok(okay) => master = okay,
-        Err(error) => match error {
-            ListUpdateError::ChannelIssue(e) => match e {
-                ChannelVideosFail::NoVideos | ChannelVideosFail::BadKey => {
-                    panic!("Should be unreachable! bad key or no videos!")
-                }
-                ChannelVideosFail::CurlFailure(e) => {
-                    // we need to go deeper.
-                    match e {
-                        CurlFail::SomethingBroke(e) => {
-                            print!("Unknown curl failure during first list build! : {e}");
-                        }
-                        CurlFail::BadURL | CurlFail::DataIssue | CurlFail::HeaderIssue => {
-                            panic!("Should be unreachable! BadURL DataIssue HeaderIssue")
-                        }
-                    }
-                }
-                ChannelVideosFail::SomethingElse(e) => {
-                    print!("Unknown failure during first list build! : {e}");
-                    std::process::exit(1)
-                }
-            },
-            ListUpdateError::SomethingElse(e) => {
-                print!("Unknown failure during first list build! : {e}");
+    // Fill in whatever the command line left out from the config file.
+    let settings: config::Settings =
+        match config::load(args.config.as_deref()).and_then(|file| config::resolve(&args, file)) {
+            Ok(okay) => okay,
+            Err(error) => {
+                eprintln!("{}", error.red());
                 std::process::exit(1)
             }
-        },
-    }
-    println!("{}", "Done!".green());
-    println!("Grabbing comments...");
-    master = match queue_comments(master, api_key) {
-        Ok(okay) => okay,
-        Err(error) => match error {
-            CommentQueueFail::SomethingElse(error) => panic!("{error:?}"),
-            CommentQueueFail::CommentFailed(_) => todo!(),
-            CommentQueueFail::CurlFailed(_) => todo!(),
-        },
-    };
-    println!("{}", "Done!".green());
-
-    for video in master {
-        println!("{}", format!("{}:\n", video.title).cyan());
-        for comment in video.queued_comments {
-            println!(
-                "{}: {}\n",
-                comment.author_name.to_string().blue(),
-                comment.content
-            );
-        }
-    }
-}
+        };
 
-fn init() -> Args {
-    //setup and tests!
+    // The library doesn't print anything, it tells us instead.
+    notice::set_handler(print_notice);
 
-    // Grab the Token from CLI
-    let args: Args = Args::parse();
-    let api_key: &str = &args.api_key;
-    let channel_id: &str = &args.channel_id;
+    let mut channels: Vec<Channel> = init(&settings);
 
-    // Test the token.
-    println!("Testing API key and channel ID...");
-    let channel_name: String;
-    match test_channel_and_key(channel_id, api_key) {
-        Ok(okay) => channel_name = okay,
-        Err(error) => {
-            match error {
-                KeyChannelTestFail::CurlFailure(e) => println!("Curl failed! : {e:?}"),
-                KeyChannelTestFail::BadKey => println!("{}", "Bad API key!".red()),
-                KeyChannelTestFail::BadChannel => println!("{}", "Channel does not exist!".red()),
-                KeyChannelTestFail::SomethingBroke(e) => println!("Something broke! : {e:?}"),
-            }
-            std::process::exit(1) // Cannot continue.
-        }
-    };
-
-    println!(
-        "{}{}",
-        format!("Found {channel_name:?}").green(),
-        "and API key is good!".green()
-    );
+    // Now that everything is ready to go, lets start tracking
+    // comments!
 
-    // Now get all video from the channel
-    println!("Getting channel videos...");
+    // Each feed's video list is where the TrackedVideo structs for... tracking videos live.
+    // Videos we haven't seen before only show comments newer than `start_timestamp`.
+    let (saved_videos, start_timestamp): (Vec<TrackedVideo>, u64) =
+        load_state(settings.state.as_deref());
+    hand_out_videos(&mut channels, saved_videos);
 
-    let videos: Vec<Video>;
+    // Open the archive, if we're keeping one.
+    let mut comment_archive: Option<archive::Archive> = open_archive(settings.archive.as_deref());
 
-    match get_videos_from_channel(api_key, channel_id) {
-        Ok(okay) => videos = okay,
-        Err(fail) => {
-            match fail {
-                ChannelVideosFail::NoVideos => println!("Channel appears to have no videos!"),
-                ChannelVideosFail::BadKey => println!("Key went bad?"),
-                ChannelVideosFail::CurlFailure(e) => println!("Curl failed! : {e:?}"),
-                ChannelVideosFail::SomethingElse(e) => println!("Something broke! : {e:?}"),
-            }
+    // Now we shall add all videos that currently exist on input channels
+    // and set the most recent timestamp to NOW
+    eprintln!("Building tracked videos list...");
+    let mut last_list_update: u64 = now();
+    for channel in &mut channels {
+        channel.listed_at = last_list_update;
+        if let Err(error) = channel.feed.refresh_videos(start_timestamp) {
+            eprintln!(
+                "{}",
+                format!(
+                    "Failed to build the video list for {:?}! : {error}",
+                    channel.name
+                )
+                .red()
+            );
             std::process::exit(1)
         }
     }
-    println!(
-        "{}",
-        format!("Got {} channel videos!", videos.len()).green()
-    );
-
-    // Should have some videos now!
-    // print one of them.
-
-    println!(
-        "Most recent video is {}.",
-        format!("{:?}", videos[0].title).yellow()
-    );
-
-    // Now that we're done testing, return the args back to main.
-    args
-}
-
-#[derive(Debug)]
-enum KeyChannelTestFail {
-    CurlFailure(CurlFail),
-    BadKey,
-    BadChannel,
-    SomethingBroke(String),
-}
-
-fn test_channel_and_key(channel_id: &str, key: &str) -> Result<String, KeyChannelTestFail> {
-    // Is this channel real?
-    // Build test URL:
+    eprintln!("{}", "Done!".green());
+    eprintln!("Watching for comments...");
 
-    // Base URL
-    let base_url = "channels";
+    // Set if we're sitting out the rest of the day to stay under budget.
+    let mut paused: bool = false;
 
-    // Query parameters
-    let part_param = "part=snippet";
-    let id_param = format!("id={channel_id}");
-    let fields_param = "fields=items(snippet(title))";
+    // Main polling loop, runs until interrupted.
+    loop {
+        let current_time: u64 = now();
 
-    // API Key
-    let api_key = format!("&key={}", &key);
-
-    // Combine the parts to create the full query
-    let query = format!("{API_URL}{base_url}?{part_param}&{id_param}&{fields_param}&{api_key}");
-
-    // Run the query
-    let mut result: std::result::Result<String, CurlFail> = c_get(&query);
+        // Out of quota, wait for it to reset.
+        if out_of_quota(&mut paused) {
+            std::thread::sleep(std::time::Duration::from_secs(1));
+            continue;
+        }
 
-    // Make sure that curl went well.
+        // Set by errors that will happen on every request from now on, like a revoked key.
+        // Quitting beats polling every second forever, same as at startup.
+        let mut fatal: bool = false;
+
+        // Only bother saving if something actually got checked this time around.
+        let mut changed: bool = channels
+            .iter()
+            .any(|channel| channel.feed.is_due(current_time));
+
+        // Check for new uploads every so often.
+        // Every feed gets the same list_refresh, so they all go at once.
+        if current_time.saturating_sub(last_list_update) >= settings.list_refresh {
+            changed = true;
+            fatal = refresh_channels(&mut channels, current_time);
+            // Even if that failed, wait a full refresh before trying again.
+            last_list_update = current_time;
+            eprintln!("{}", quota::summary().dimmed());
+        }
 
-    let result_string: String = match result {
-        Err(e) => return Err(KeyChannelTestFail::CurlFailure(e)),
-        Ok(s) => s,
-    };
+        for channel in &mut channels {
+            // No point in the rest of the channels failing too, out_of_quota will catch it.
+            if quota::exhausted() || fatal {
+                break;
+            }
+            // Grab comments on any videos that are due for a check.
+            // If this fails, we keep the old list and try again next time around.
+            if let Err(error) = channel.feed.poll(current_time) {
+                eprintln!(
+                    "{}",
+                    format!("Failed to grab comments for {:?}! : {error}", channel.name).red()
+                );
+                fatal |= error.is_fatal() && !error.is_quota();
+            }
 
-    let json: Value = serde_json::from_str(&result_string).unwrap(); //TODO: double unwrap! ugly!
+            // Archive and print anything new.
+            if let Some(comment_archive) = &mut comment_archive {
+                archive_queued_comments(comment_archive, &channel.feed.videos);
+            }
+            show_queued_comments(&channel.name, &mut channel.feed.videos, &settings);
+        }
 
-    match json["error"]["code"].as_i64() {
-        None => {
-            // either the test passed, or its a non-existant channel.
-            if result_string == "{}\n" {
-                // channel does not exist!
-                return Err(KeyChannelTestFail::BadChannel)
+        // Save our progress.
+        if let (true, Some(path)) = (changed, &settings.state) {
+            // All in one file, TrackedVideo.channel_id sorts them back out next time.
+            let videos: Vec<TrackedVideo> = channels
+                .iter()
+                .flat_map(|channel| channel.feed.videos.iter().cloned())
+                .collect();
+            if let Err(error) = state::save(path, &videos, current_time) {
+                eprintln!("{}", error.to_string().red());
             }
-            //otherwise we're probably fine?
-        },                                              
-        Some(400) => return Err(KeyChannelTestFail::BadKey), // Token is no good!
-        Some(0) => return Err(KeyChannelTestFail::BadChannel), // No such channel!
-        Some(_) => {
-            return Err(KeyChannelTestFail::SomethingBroke(format!(
-                "Failure checking channel! {json}"
-            )))
-        } //number other than 400!
-    }
+        }
 
-    //All good! return the channel name.
+        // Saved what we had, nothing more to do.
+        if fatal {
+            std::process::exit(1)
+        }
 
-    let title = json["items"][0]["snippet"]["title"].as_str().unwrap();
-    Ok(title.to_string())
+        std::thread::sleep(std::time::Duration::from_secs(1));
+    }
 }
 
-// Make some easier functions for Curl
-
-#[derive(Debug)]
-enum CurlFail {
-    SomethingBroke(String),
-    BadURL,
-    DataIssue,
-    HeaderIssue,
+fn refresh_channels(channels: &mut [Channel], current_time: u64) -> bool {
+    // Look for new videos on every channel. True if it failed in a way that won't get better.
+    let mut fatal: bool = false;
+    for channel in channels {
+        // Videos found now showed up since the last look, so only comments since then
+        // are new. Not all of them, an old video added to a playlist (or made public)
+        // would flood the feed with its whole history.
+        match channel.feed.refresh_videos(channel.listed_at) {
+            Ok(()) => channel.listed_at = current_time,
+            Err(error) => {
+                eprintln!(
+                    "{}",
+                    format!(
+                        "Failed to update video list for {:?}! : {error}",
+                        channel.name
+                    )
+                    .red()
+                );
+                fatal |= error.is_fatal() && !error.is_quota();
+            }
+        }
+    }
+    fatal
 }
 
-fn c_get(input: &str) -> std::result::Result<String, CurlFail> {
-    use std::sync::{Arc, Mutex};
-    // create an easy from CURL
-    let mut curl = Easy::new();
-    // Arc and Mutex for shared mutability, lets us use the data in the closure.
-    let data = Arc::new(Mutex::new(Vec::new()));
-
-    // Set the URL
-
-    match curl.url(input) {
-        Ok(_) => (),
-        Err(_) => return Err(CurlFail::BadURL),
+fn load_state(path: Option<&Path>) -> (Vec<TrackedVideo>, u64) {
+    // Pick up where we left off, if we can.
+    // Otherwise start with nothing, and only show comments from now on.
+    let Some(path) = path else {
+        return (Vec::new(), now());
+    };
+    match state::load(path) {
+        Ok(Some(saved)) => {
+            eprintln!(
+                "Resuming {} tracked videos from last run.",
+                saved.videos.len()
+            );
+            // Anything uploaded while we were gone still gets its comments shown.
+            (saved.videos, saved.saved_at)
+        }
+        Ok(None) => {
+            eprintln!("No saved state yet, starting fresh.");
+            (Vec::new(), now())
+        }
+        Err(error) => {
+            eprintln!("{}", error.to_string().red());
+            std::process::exit(1)
+        }
     }
+}
 
-    // Set headers
-    let mut headers = List::new();
-
-    match headers.append("Accept: application/json") {
-        Ok(_) => (),
-        Err(_) => return Err(CurlFail::HeaderIssue),
+fn hand_out_videos(channels: &mut [Channel], saved_videos: Vec<TrackedVideo>) {
+    // Give every saved video back to the feed it came from.
+    // Videos nothing is watching anymore get dropped.
+    for mut video in saved_videos {
+        // Saved before there could be more than one channel, so it's the first one's.
+        if video.channel_id.is_empty() {
+            video.channel_id.clone_from(&channels[0].feed.channel_id);
+        }
+        // Whoever had it at startup, or failing that (it's gone private or something),
+        // whoever's channel it's on.
+        let owner: Option<usize> = channels
+            .iter()
+            .position(|channel| channel.video_ids.contains(&video.video_id))
+            .or_else(|| {
+                channels.iter().position(|channel| {
+                    channel.feed.source == VideoSource::Channel
+                        && channel.feed.channel_id == video.channel_id
+                })
+            });
+        if let Some(owner) = owner {
+            channels[owner].feed.videos.push(video);
+        }
     }
+}
 
-    match curl.http_headers(headers) {
-        Ok(_) => (),
-        Err(_) => return Err(CurlFail::HeaderIssue),
+fn open_archive(path: Option<&Path>) -> Option<archive::Archive> {
+    // Open (or create) the archive, if we're keeping one.
+    match archive::Archive::open(path?) {
+        Ok(okay) => Some(okay),
+        Err(error) => {
+            eprintln!("{}", error.to_string().red());
+            std::process::exit(1)
+        }
     }
+}
 
-    // Clone the Arc for the closure.
-    let cloned_data = Arc::clone(&data);
-
-    // Set a closure to write data to our Vec<u8>.
-    let tmp = curl.write_function(move |response_data: &[u8]| {
-        cloned_data.lock().unwrap().extend_from_slice(response_data);
-        Ok(response_data.len())
-    });
-
-    match tmp {
-        Ok(_) => (),
-        Err(_) => return Err(CurlFail::DataIssue),
+fn out_of_quota(paused: &mut bool) -> bool {
+    // Checks if we've hit the daily budget, and lets the user know when we pause or unpause.
+    if quota::exhausted() {
+        if !*paused {
+            *paused = true;
+            if quota::slowing_down() {
+                eprintln!(
+                    "{}",
+                    "Google says we're going too fast! Pausing for a minute.".yellow()
+                );
+            } else {
+                eprintln!("{}", quota::summary().yellow());
+                eprintln!(
+                    "{}",
+                    format!(
+                        "Daily quota budget used up! Pausing for {} minutes until it resets.",
+                        quota::seconds_until_reset() / 60
+                    )
+                    .yellow()
+                );
+            }
+        }
+        return true;
     }
-
-    match curl.perform() {
-        Ok(_) => (),
-        Err(e) => return Err(CurlFail::SomethingBroke(e.to_string())),
+    if *paused {
+        *paused = false;
+        eprintln!("{}", "Quota is back, back to watching!".green());
     }
-
-    // Convert the Vec<u8> to a String.
-    let response_string: String = String::from_utf8_lossy(&data.lock().unwrap()).to_string();
-    Ok(response_string)
+    false
 }
 
-#[derive(Debug, Clone)]
-struct YTComment {
-    content: String,
-    author_name: String,
-    timestamp: u64,
-}
-
-#[derive(Debug)]
-enum CommentFail {
-    NoComments,
-    BadKey,
-    CurlFailure(CurlFail),
-    SomethingElse(String),
-    EpochFail,
+fn archive_queued_comments(comment_archive: &mut archive::Archive, video_list: &[TrackedVideo]) {
+    // Stash every queued comment in the archive before they get printed and dropped.
+    for video in video_list {
+        if video.queued_comments.is_empty() {
+            continue;
+        }
+        if let Err(error) =
+            comment_archive.store(&video.video_id, &video.title, &video.queued_comments)
+        {
+            // Not worth stopping the feed over, but make some noise about it.
+            eprintln!("{}", error.to_string().red());
+        }
+    }
 }
 
-fn get_comments_from_video(
-    key: &str,
-    video_id: &str,
-    amount: i8,
-) -> Result<Vec<YTComment>, CommentFail> {
-    //TODO: Filter out comments from self
-    //https://www.googleapis.com/youtube/v3/commentThreads?key=[KEY]&textFormat=plainText&part=snippet&videoId=[VIDEO_ID]&maxResults=[AMOUNT]]
-
-    // Create the Curl address.
-    let rq_type = "commentThreads?";
-    let key = format!("key={key}&");
-    let format = "textFormat=plainText&";
-    let part = "part=snippet&";
-    let vid_id = format!("videoId={video_id}&");
-    let num_results = format!("maxResults={amount}");
-    let fields = "&fields=items(snippet(topLevelComment(snippet(authorDisplayName%2CtextOriginal%2CpublishedAt))))";
-    let url = format!("{API_URL}{rq_type}{key}{format}{part}{vid_id}{num_results}{fields}");
-
-    // Run the query
-    let _result: Result<String, CurlFail> = c_get(&url);
-
-    // Roll up errors
-    let json: String = match _result {
-        Ok(okay) => okay,
-        Err(error) => return Err(CommentFail::CurlFailure(error)),
-    };
-
-    // We've got good JSON, time to pull the comments out of it.
-    let unwrapped_json: Value = serde_json::from_str(&json).unwrap();
-
-    // First we need to check if we were given an error code.
-
-    match unwrapped_json["error"]["code"].as_i64() {
-        None => (),                                   // No error means test passed!
-        Some(400) => return Err(CommentFail::BadKey), // Token is no good!
-        Some(code) => {
-            panic!("Unknown response code! : {code} :: {unwrapped_json:?}")
+fn show_queued_comments(
+    channel_name: &str,
+    video_list: &mut [TrackedVideo],
+    settings: &config::Settings,
+) {
+    // Show and clear the comment queue of every video that has something waiting.
+    // Filtered out comments are dropped here, the archive already has them.
+    for video in video_list {
+        // Oldest first, so the feed reads top to bottom.
+        video
+            .queued_comments
+            .sort_by_key(|comment| comment.timestamp);
+        let shown: Vec<YTComment> = video
+            .queued_comments
+            .drain(..)
+            .filter(|comment| settings.filter.allows(comment))
+            .collect();
+        if shown.is_empty() {
+            continue;
         }
-    };
-
-    // Okay, now that we know we have a good comment pull, lets scrape those comments out!
-
-    // Json structure is as follows:
-    //{
-    //"items": [
-    //  {
-    //    "snippet": {
-    //      "topLevelComment": {
-    //        "snippet": {
-    //          "textOriginal": "TEXT",
-    //          "authorDisplayName": "NAME",
-    //          "publishedAt": "TIME"
-    //        }
-    //      }
-    //    }
-    //  },
-
-    let mut return_vec: Vec<YTComment> = Vec::new();
-
-    let items_array = unwrapped_json["items"].as_array().unwrap();
-
-    for item in items_array {
-        let snippet = &item["snippet"];
-        let top_level_comment = &snippet["topLevelComment"]["snippet"];
-
-        let wrapped: YTComment = YTComment {
-            content: top_level_comment["textOriginal"]
-                .as_str()
-                .unwrap_or_default()
-                .to_string(),
-            author_name: top_level_comment["authorDisplayName"]
-                .as_str()
-                .unwrap_or_default()
-                .to_string(),
-            timestamp: {
-                let time_string = top_level_comment["publishedAt"].as_str().unwrap();
-                let timestamp = DateTime::parse_from_rfc3339(time_string).unwrap();
-                timestamp.timestamp().try_into().unwrap()
-            },
-        };
-
-        // push that comment!
-        return_vec.push(wrapped);
-    }
 
-    // Now check to make sure we got some comments before returning
-    if return_vec.is_empty() {
-        // No comments!
-        return Err(CommentFail::NoComments);
+        for comment in &shown {
+            let payload: serde_json::Value = output::comment_json(channel_name, video, comment);
+            if settings.format == output::OutputFormat::Json {
+                println!("{payload}");
+            }
+            output::notify(&settings.sinks, &payload);
+        }
+        if settings.format == output::OutputFormat::Text {
+            print_comments(&format!("[{channel_name}] {}", video.title), shown);
+        }
     }
-    Ok(return_vec)
-}
-
-#[derive(Debug)]
-enum ChannelVideosFail {
-    NoVideos,
-    BadKey,
-    CurlFailure(CurlFail),
-    SomethingElse(String),
 }
 
-#[derive(Debug)]
-struct Video {
-    title: String,
-    id: String,
+fn print_notice(notice: &Notice) {
+    let text: String = notice.to_string();
+    match notice {
+        Notice::Request { .. } | Notice::Retrying { .. } => eprintln!("{}", text.dimmed()),
+        Notice::SwitchedKey(_)
+        | Notice::CommentsDisabled { .. }
+        | Notice::VideoGone { .. }
+        | Notice::PagesRanOut { .. } => {
+            eprintln!("{}", text.yellow());
+        }
+        Notice::CommentsBack { .. } => eprintln!("{}", text.green()),
+        Notice::VideoFailed { .. } => eprintln!("{}", text.red()),
+    }
 }
 
-fn get_videos_from_channel(key: &str, channel_id: &str) -> Result<Vec<Video>, ChannelVideosFail> {
-    // Lets get those videos
-    // Create the URL for the API request
-    let function = "search?part=snippet";
-    let max_results = "&maxResults=4294967295";
-    let order = "&order=date";
-    let fields = "&fields=items(id(videoId)%2Csnippet(title))";
-    let api_key = format!("&key={key}");
-    let channel_param = format!("&channelId={channel_id}");
-    let query = format!("{API_URL}{function}{max_results}{order}{fields}{api_key}{channel_param}");
-
-    // run that query
-    let result: Result<String, CurlFail> = c_get(&query);
-
-    // Handle them errors.
-
-    // Roll up errors
-    let json: String = match result {
-        Ok(okay) => okay,
-        Err(error) => return Err(ChannelVideosFail::CurlFailure(error)),
-    };
-
-    // Good stuff, crack it open.
-    let unwrapped_json: Value = serde_json::from_str(&json).unwrap();
-
-    // Error handling again
-
-    match unwrapped_json["error"]["code"].as_i64() {
-        None => (),                                         // No error means test passed!
-        Some(400) => return Err(ChannelVideosFail::BadKey), // Token is no good!
-        Some(code) => {
-            return Err(ChannelVideosFail::SomethingElse(format!(
-                "Unknown response code! : {code}"
-            )))
-        }
-    };
+fn print_comments(title: &str, comments: Vec<YTComment>) {
+    // Prints a video's new comments, with replies tucked under their threads.
+    println!("{}", format!("{title}:\n").cyan());
+
+    // Split the replies off so they can go under their threads.
+    let (replies, top_level): (Vec<YTComment>, Vec<YTComment>) = comments
+        .into_iter()
+        .partition(|comment| comment.reply_to.is_some());
+
+    // New comments first, with any new replies right under them.
+    let mut printed_threads: Vec<&str> = Vec::new();
+    for comment in &top_level {
+        print_comment(comment);
+        print_replies(&replies, &comment.thread_id);
+        printed_threads.push(&comment.thread_id);
+    }
 
-    // return format should match
-    // {
-    //     "items": [
-    //       {
-    //         "id": {
-    //           "videoId": "ID"
-    //         },
-    //         "snippet": {
-    //           "title": "TITLE"
-    //         }
-    //       },
-    // }
-
-    let mut return_vec: Vec<Video> = Vec::new();
-
-    let items_array = unwrapped_json["items"].as_array().unwrap();
-    let bad_chars = &['\"']; // Dont want these in our titles
-
-    // Pull those titles and ID's out!
-
-    for item in items_array {
-        // check for nulls
-        if item["snippet"]["title"] == Value::Null || item["id"]["videoId"] == Value::Null {
-            // Nulls are a no-no, skip
+    // Then new replies on older comments, one thread at a time.
+    for reply in &replies {
+        if printed_threads.contains(&reply.thread_id.as_str()) {
             continue;
         }
-        let wrapped: Video = Video {
-            title: item["snippet"]["title"]
-                .to_string()
-                .trim()
-                .replace(bad_chars, "")
-                .to_string(),
-            id: item["id"]["videoId"]
-                .to_string()
-                .trim()
-                .replace(bad_chars, "")
-                .to_string(),
-        };
-        // onto the vec it goes
-        return_vec.push(wrapped);
+        let parent: &str = reply.reply_to.as_deref().unwrap_or_default();
+        println!("{}", format!("In reply to {parent}:\n").dimmed());
+        print_replies(&replies, &reply.thread_id);
+        printed_threads.push(&reply.thread_id);
     }
+}
 
-    Ok(return_vec)
+fn print_replies(replies: &[YTComment], thread_id: &str) {
+    // Print every reply on a thread, indented under it.
+    for reply in replies.iter().filter(|reply| reply.thread_id == thread_id) {
+        print_reply(reply);
+    }
 }
 
-#[derive(Debug)]
-enum ListUpdateError {
-    ChannelIssue(ChannelVideosFail),
-    SomethingElse(String),
+fn print_comment(comment: &YTComment) {
+    println!("{}: {}\n", comment.author_name.blue(), comment.content);
 }
 
-fn update_video_list(
-    old: Vec<TrackedVideo>,
-    channel_id: &str,
-    key: &str,
-) -> Result<Vec<TrackedVideo>, ListUpdateError> {
-    // This function takes in the list of videos, checks the channel to see
-    // if there are videos on the channel that do not exist in the list yet.
-
-    // grab all of the videos off of the channel
-    let current_videos = match get_videos_from_channel(key, channel_id) {
-        Ok(okay) => okay,
-        Err(error) => return Err(ListUpdateError::ChannelIssue(error)),
-    };
+fn print_reply(reply: &YTComment) {
+    println!("    ↳ {}: {}\n", reply.author_name.blue(), reply.content);
+}
 
-    // Video list is good, now lets compare.
+fn init(settings: &config::Settings) -> Vec<Channel> {
+    //setup and tests!
 
-    // If the length of the 2 Vec are the same, its pretty safe to assume there hasnt been a new video.
+    quota::set_budget(settings.quota_budget);
 
-    if old.len() == current_videos.len() {
-        // The same! exit early
-        return Ok(old);
+    if settings.min_delay > settings.max_delay {
+        eprintln!("{}", "min_delay cannot be larger than max_delay!".red());
+        std::process::exit(1)
     }
 
-    // Build the new videos into a TrackedVideo
-    let mut new_tracked_videos: Vec<TrackedVideo> = Vec::new();
-
-    for i in current_videos {
-        new_tracked_videos.push(TrackedVideo {
-            title: i.title,
-            video_id: i.id,
-            most_recent_timestamp: 0,
-            queued_comments: [].to_vec(),
-            recheck_delay: 10,
-        });
+    if settings.api_keys.len() > 1 {
+        eprintln!("Rotating through {} API keys.", settings.api_keys.len());
     }
 
-    // Combine the new list with the old list
-    // and remove dupes.
-
-    //`old` comes first to make sure we discard matching news, not olds.
-    let mut output: Vec<TrackedVideo> = old
+    // A feed per channel, plus one for any picked out videos and one for the playlist.
+    let mut sources: Vec<(&str, VideoSource)> = settings
+        .channel_ids
         .iter()
-        .chain(new_tracked_videos.iter())
-        .cloned()
+        .map(|channel_id| (channel_id.as_str(), VideoSource::Channel))
         .collect();
-    output.sort_by(|a, b| a.video_id.cmp(&b.video_id));
-    output.dedup_by(|a, b| a.video_id == b.video_id);
-
-    // we're done!
-    Ok(output)
-}
+    if !settings.video_ids.is_empty() {
+        sources.push(("", VideoSource::Videos(settings.video_ids.clone())));
+    }
+    if let Some(playlist_id) = &settings.playlist_id {
+        sources.push(("", VideoSource::Playlist(playlist_id.clone())));
+    }
 
-enum CommentQueueFail {
-    SomethingElse(String),
-    CommentFailed(CommentFail),
-    CurlFailed(CurlFail),
-}
+    let mut channels: Vec<Channel> = Vec::new();
+    let mut any_videos: bool = false;
+    for (channel_id, source) in sources {
+        let mut channel: Channel = check_feed(settings, channel_id, source);
+
+        // Now get all video from the channel
+        eprintln!("Getting videos...");
+        match channel.feed.list_videos() {
+            Ok(videos) => {
+                any_videos |= show_videos(&channel.name, &videos);
+                channel.video_ids = videos.into_iter().map(|video| video.id).collect();
+            }
+            Err(error) => {
+                eprintln!(
+                    "{}",
+                    format!("Failed to get channel videos! : {error}").red()
+                );
+                std::process::exit(1)
+            }
+        }
+        channels.push(channel);
+    }
 
-fn queue_comments(
-    video_list: Vec<TrackedVideo>,
-    key: &str,
-) -> Result<Vec<TrackedVideo>, CommentQueueFail> {
-    // This function takes in a list of tracked videos, and updates each entry with
-    // new comments on those videos.
+    // A channel with nothing on it yet is fine, as long as something is being watched.
+    if !any_videos {
+        eprintln!("No videos to watch!");
+        std::process::exit(1)
+    }
 
-    let mut output_list: Vec<TrackedVideo> = Vec::new();
+    // Now that we're done testing, hand the channels back to main.
+    channels
+}
 
-    // Loop over each video in the list!
+fn new_client(settings: &config::Settings) -> Box<dyn HttpClient> {
+    let mut client = CurlClient::new();
+    client.verbose = settings.verbose;
+    if settings.api_keys.len() > 1 {
+        Box::new(KeyRotation::new(
+            Box::new(client),
+            settings.api_keys.clone(),
+        ))
+    } else {
+        Box::new(client)
+    }
+}
 
-    for video in video_list {
-        // TODO: timed update checking.
-        // if update not needed continue
-
-        // Grab the most recent comments from this video
-        // TODO: Increase comments retrieved until we find one with timestamp that older than video.most_recent_timestamp
-
-        let comments: Vec<YTComment> = match get_comments_from_video(key, &video.video_id, 5) {
-            Ok(messages) => messages,
-            Err(error) => match error {
-                CommentFail::NoComments => continue, // There are no comments, so there cant be any new ones either!
-                CommentFail::BadKey | CommentFail::EpochFail => {
-                    return Err(CommentQueueFail::CommentFailed(error))
-                }
-                CommentFail::CurlFailure(error) => return Err(CommentQueueFail::CurlFailed(error)),
-                CommentFail::SomethingElse(error) => {
-                    return Err(CommentQueueFail::SomethingElse(error))
-                }
-            },
-        };
+fn check_feed(settings: &config::Settings, channel_id: &str, source: VideoSource) -> Channel {
+    // Whatever key is in the URLs gets swapped out when rotating, so the first one will do.
+    let first_key: &str = settings.api_keys.first().map_or("", String::as_str);
+    let mut feed = Feed::new(first_key, channel_id);
+    feed.schedule = PollSchedule {
+        min_delay: settings.min_delay,
+        max_delay: settings.max_delay,
+    };
+    feed.limits = FetchLimits {
+        max_pages: settings.max_pages,
+        parallel: usize::from(settings.parallel),
+    };
+    feed.list_refresh = settings.list_refresh;
+    feed.client = new_client(settings);
+    let what: String = match &source {
+        VideoSource::Channel => format!("channel ID {channel_id}"),
+        VideoSource::Playlist(playlist_id) => format!("playlist {playlist_id}"),
+        VideoSource::Videos(video_ids) => format!("{} videos", video_ids.len()),
+    };
+    feed.source = source;
 
-        // Now check if the comments are newer than most_recent_timestamp
-        let mut new_comments: Vec<YTComment> = Vec::new();
-        let mut out_updated: TrackedVideo = video.clone();
-        let mut new_max_timestamp: u64 = video.most_recent_timestamp;
-
-        for comment in comments {
-            if comment.timestamp >= video.most_recent_timestamp {
-                // comment is new! add to the list!
-                // update the new timestamp if its the new best
-                if comment.timestamp >= new_max_timestamp {
-                    new_max_timestamp = comment.timestamp;
-                }
-
-                // add it to the comment buffer!
-                new_comments.push(comment);
+    // Test the token.
+    eprintln!("Testing API key and {what}...");
+    let channel_name: String;
+    match feed.check() {
+        Ok(okay) => channel_name = okay,
+        Err(error) => {
+            eprintln!("{}", error.to_string().red());
+            if error.is_quota() {
+                eprintln!(
+                    "{}",
+                    format!(
+                        "It resets in {} minutes.",
+                        quota::seconds_until_reset() / 60
+                    )
+                    .red()
+                );
             }
+            std::process::exit(1) // Cannot continue.
         }
+    }
 
-        // Add the comments (if there are any) to the TrackedVideo
-        out_updated.queued_comments.append(&mut new_comments);
-
-        // Apply the new most recent timestamp
-        out_updated.most_recent_timestamp = new_max_timestamp;
+    // Handles and links have been looked up by now, so this is the real ID.
+    let found: String = match &feed.source {
+        VideoSource::Channel => format!("Found {channel_name:?} ({}) ", feed.channel_id),
+        _ => format!("Found {channel_name:?} "),
+    };
+    eprintln!("{}{}", found.green(), "and API key is good!".green());
 
-        // Now push the updated video to the output
-        output_list.push(out_updated);
+    Channel {
+        name: channel_name,
+        feed,
+        video_ids: Vec::new(),
+        listed_at: 0,
     }
+}
+
+fn show_videos(channel_name: &str, videos: &[Video]) -> bool {
+    // Says what was found on a channel, and whether there was anything at all.
+    let Some(most_recent) = videos.first() else {
+        eprintln!(
+            "{}",
+            format!("{channel_name:?} appears to have no videos!").yellow()
+        );
+        return false;
+    };
+    eprintln!("{}", format!("Got {} videos!", videos.len()).green());
 
-    // All the comments should be updated now!
-    Ok(output_list)
+    // Should have some videos now!
+    // print one of them.
+
+    eprintln!(
+        "Most recent video is {}.",
+        format!("{:?}", most_recent.title).yellow()
+    );
+    true
 }
@@ -0,0 +1,89 @@
+//! Things worth telling someone about that aren't errors, like a retry or a deleted video.
+//!
+//! The library never prints anything itself. Notices go to whatever [`set_handler`] was given,
+//! and nowhere at all until then. The handler is process-wide, same as the
+//! [quota ledger](crate::quota) and the [hidden secrets](crate::redact).
+
+use std::sync::{PoisonError, RwLock};
+
+/// Something that happened along the way.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Notice {
+    /// How a request went, with [`CurlClient::verbose`](crate::CurlClient::verbose) on.
+    Request {
+        endpoint: String,
+        outcome: String,
+        millis: u128,
+    },
+    /// A request failed, and is getting another go after `wait_ms`.
+    Retrying { failure: String, wait_ms: u64 },
+    /// [`KeyRotation`](crate::KeyRotation) moved on to another key, counting from 1.
+    SwitchedKey(usize),
+    /// A video's uploader turned comments off. It gets checked every `recheck_hours` in case
+    /// they come back.
+    CommentsDisabled { title: String, recheck_hours: u64 },
+    /// Comments got turned back on.
+    CommentsBack { title: String },
+    /// A video was deleted or made private, and isn't being watched anymore.
+    VideoGone { title: String },
+    /// Checking one video failed. It gets tried again later.
+    VideoFailed { title: String, error: String },
+    /// A video got more new comments than fit in `max_pages` pages, and the older ones were
+    /// skipped.
+    PagesRanOut { video_id: String, max_pages: u16 },
+}
+
+impl std::fmt::Display for Notice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Request {
+                endpoint,
+                outcome,
+                millis,
+            } => write!(f, "{endpoint} {outcome} in {millis}ms"),
+            Self::Retrying { failure, wait_ms } => {
+                write!(f, "Request failed ({failure}), retrying in {wait_ms}ms...")
+            }
+            Self::SwitchedKey(number) => write!(f, "Switching to API key {number}."),
+            Self::CommentsDisabled {
+                title,
+                recheck_hours,
+            } => write!(
+                f,
+                "Comments are disabled on {title:?}, checking again every {recheck_hours} hours."
+            ),
+            Self::CommentsBack { title } => write!(f, "Comments are back on for {title:?}!"),
+            Self::VideoGone { title } => write!(f, "{title:?} is gone, no longer tracking it."),
+            Self::VideoFailed { title, error } => {
+                write!(f, "Failed to grab comments on {title:?}! : {error}")
+            }
+            Self::PagesRanOut {
+                video_id,
+                max_pages,
+            } => write!(
+                f,
+                "More new comments on {video_id} than fit in {max_pages} pages, some were skipped."
+            ),
+        }
+    }
+}
+
+type Handler = Box<dyn Fn(&Notice) + Send + Sync>;
+
+static HANDLER: RwLock<Option<Handler>> = RwLock::new(None);
+
+/// Hands every notice from now on to `handler`, instead of the last one. Gets called from
+/// whichever thread the notice came up on.
+pub fn set_handler(handler: impl Fn(&Notice) + Send + Sync + 'static) {
+    *HANDLER.write().unwrap_or_else(PoisonError::into_inner) = Some(Box::new(handler));
+}
+
+pub(crate) fn send(notice: &Notice) {
+    if let Some(handler) = HANDLER
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .as_ref()
+    {
+        handler(notice);
+    }
+}
@@ -0,0 +1,161 @@
+// Where new comments end up: which ones get shown, what they look like, and who else hears
+// about them.
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use colored::Colorize;
+use curl::easy::{Easy, List};
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use youtube_comment_feed::{TrackedVideo, YTComment};
+
+// How comments get printed.
+#[derive(clap::ValueEnum, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    // Colored, with replies under their threads.
+    #[default]
+    Text,
+    // One JSON object per comment, one per line.
+    Json,
+}
+
+// Somewhere else to send every comment that gets shown.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Sink {
+    // Run through `sh -c`, with the comment as JSON on stdin.
+    Command(String),
+    // POSTed the comment as JSON.
+    Webhook(String),
+}
+
+// Which comments are worth showing.
+#[derive(Debug, Default)]
+pub struct CommentFilter {
+    pub include: Vec<Regex>, // If there are any, comments have to match one.
+    pub exclude: Vec<Regex>, // Comments matching any of these are hidden.
+    pub ignore_authors: Vec<String>, // Names or channel IDs to hide comments from.
+}
+
+impl CommentFilter {
+    pub fn build(
+        include: &[String],
+        exclude: &[String],
+        ignore_authors: &[String],
+    ) -> Result<Self, String> {
+        // Compiles the patterns, so a typo shows up at startup instead of never matching.
+        let compile = |patterns: &[String]| -> Result<Vec<Regex>, String> {
+            patterns
+                .iter()
+                .map(|pattern| {
+                    Regex::new(pattern).map_err(|error| format!("Bad filter regex! : {error}"))
+                })
+                .collect()
+        };
+        Ok(Self {
+            include: compile(include)?,
+            exclude: compile(exclude)?,
+            ignore_authors: ignore_authors.to_vec(),
+        })
+    }
+
+    pub fn allows(&self, comment: &YTComment) -> bool {
+        let ignored: bool = self.ignore_authors.iter().any(|author| {
+            *author == comment.author_name || Some(author) == comment.author_channel_id.as_ref()
+        });
+        let included: bool = self.include.is_empty()
+            || self
+                .include
+                .iter()
+                .any(|regex| regex.is_match(&comment.content));
+        let excluded: bool = self
+            .exclude
+            .iter()
+            .any(|regex| regex.is_match(&comment.content));
+        !ignored && included && !excluded
+    }
+}
+
+pub fn comment_json(channel_name: &str, video: &TrackedVideo, comment: &YTComment) -> Value {
+    // What comments look like in JSON output, and what the sinks get sent.
+    json!({
+        "channel_id": video.channel_id,
+        "channel_name": channel_name,
+        "video_id": video.video_id,
+        "video_title": video.title,
+        "id": comment.id,
+        "thread_id": comment.thread_id,
+        "reply_to": comment.reply_to,
+        "author_name": comment.author_name,
+        "author_channel_id": comment.author_channel_id,
+        "content": comment.content,
+        "like_count": comment.like_count,
+        "timestamp": comment.timestamp,
+    })
+}
+
+pub fn notify(sinks: &[Sink], payload: &Value) {
+    // Hands a comment to every sink. A broken sink gets complained about, but never stops
+    // the feed.
+    for sink in sinks {
+        let result: Result<(), String> = match sink {
+            Sink::Command(command) => run_command(command, payload),
+            Sink::Webhook(url) => post_webhook(url, payload),
+        };
+        if let Err(error) = result {
+            eprintln!("{}", format!("Notification failed! : {error}").red());
+        }
+    }
+}
+
+fn run_command(command: &str, payload: &Value) -> Result<(), String> {
+    // Waits for the command to finish, so keep it quick.
+    let mut child = match Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::piped())
+        .spawn()
+    {
+        Ok(okay) => okay,
+        Err(e) => return Err(format!("Couldn't run {command:?} : {e}")),
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+        // Commands that don't read stdin close it early, that's fine.
+        let _ = writeln!(stdin, "{payload}");
+    }
+    match child.wait() {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => Err(format!("{command:?} exited with {status}")),
+        Err(e) => Err(format!("{command:?} : {e}")),
+    }
+}
+
+fn post_webhook(url: &str, payload: &Value) -> Result<(), String> {
+    // Not youtube, so this doesn't go through the feed's client (or count against the quota).
+    let mut curl = Easy::new();
+    let mut headers = List::new();
+    let setup: Result<(), curl::Error> = curl
+        .url(url)
+        .and_then(|()| headers.append("Content-Type: application/json"))
+        .and_then(|()| curl.http_headers(headers))
+        .and_then(|()| curl.post_fields_copy(payload.to_string().as_bytes()))
+        .and_then(|()| curl.timeout(Duration::from_secs(10)));
+    match setup {
+        Ok(()) => (),
+        Err(e) => return Err(format!("Couldn't set up the webhook! : {e}")),
+    }
+    match curl.perform() {
+        Ok(()) => (),
+        Err(e) => return Err(format!("Webhook {url:?} : {e}")),
+    }
+    match curl.response_code() {
+        Ok(code) if code < 300 => Ok(()),
+        Ok(code) => Err(format!("Webhook {url:?} answered {code}")),
+        Err(e) => Err(format!("Webhook {url:?} : {e}")),
+    }
+}
@@ -0,0 +1,498 @@
+//! Keeping track of how much of the daily API quota we've spent.
+//! Google resets quota at midnight Pacific time, so days here are Pacific days.
+//!
+//! Every key gets its own quota. Keys registered with [`add_key`] are tracked separately, and
+//! we're only out once all of them are. See [`KeyRotation`](crate::http::KeyRotation).
+//!
+//! There's one ledger for the whole process, not one per [`Feed`](crate::Feed). Every feed
+//! shares the one budget from [`set_budget`], and once we're [out](exhausted), every feed
+//! waits for the reset.
+
+use std::collections::BTreeMap;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use chrono::{DateTime, Duration, NaiveDate, TimeZone, Utc};
+use chrono_tz::US::Pacific;
+
+use crate::{now, API_URL};
+
+/// Google's default daily quota for a project.
+pub const DEFAULT_BUDGET: u64 = 10_000;
+
+// How much of the budget we're allowed to use up front before pacing kicks in,
+// so a fresh start (or a fresh day) can do its first sweep at full speed.
+const BURST_PERCENT: u64 = 10;
+
+// How many seconds to hold off for when Google says we're going too fast. That's a limit
+// on requests per minute or so, not per day.
+const RATE_LIMIT_COOLDOWN: u64 = 60;
+
+struct QuotaLedger {
+    day: Option<NaiveDate>,              // The Pacific day these numbers are for.
+    used: u64,                           // Units spent today.
+    calls: BTreeMap<String, (u64, u64)>, // Calls and units spent today, per endpoint.
+    budget: u64,                         // Units we want to stay under per day, per key.
+    cut_off: bool,                       // Google told us we're out, whatever our count says.
+    slow_until: u64,                     // Google told us to slow down, so wait until this time.
+    keys: Vec<KeyUsage>,                 // Keys being rotated through. Empty if there's just one.
+}
+
+struct KeyUsage {
+    key: String,
+    used: u64,       // Units spent today with this key.
+    cut_off: bool,   // Google told us this key is out for today.
+    slow_until: u64, // Google told us this key is going too fast, so wait until this time.
+    invalid: bool,   // Google told us this key doesn't work at all. Stays that way.
+}
+
+impl KeyUsage {
+    const fn usable(&self, budget: u64, time: u64) -> bool {
+        !self.invalid && !self.cut_off && self.used < budget && time >= self.slow_until
+    }
+}
+
+// Every request goes through `CurlClient`, which doesn't have anywhere to keep this, so it lives here.
+static LEDGER: Mutex<QuotaLedger> = Mutex::new(QuotaLedger {
+    day: None,
+    used: 0,
+    calls: BTreeMap::new(),
+    budget: DEFAULT_BUDGET,
+    cut_off: false,
+    slow_until: 0,
+    keys: Vec::new(),
+});
+
+fn ledger() -> MutexGuard<'static, QuotaLedger> {
+    // A panic mid-update can't leave the numbers in a state worth giving up over.
+    let mut ledger = LEDGER.lock().unwrap_or_else(PoisonError::into_inner);
+    ledger.roll_over(Utc::now());
+    ledger
+}
+
+impl QuotaLedger {
+    fn roll_over(&mut self, time: DateTime<Utc>) {
+        // Start fresh if it's a new day in California.
+        let today: NaiveDate = time.with_timezone(&Pacific).date_naive();
+        if self.day != Some(today) {
+            self.day = Some(today);
+            self.used = 0;
+            self.calls.clear();
+            self.cut_off = false;
+            for key in &mut self.keys {
+                key.used = 0;
+                key.cut_off = false;
+            }
+        }
+    }
+
+    fn key(&mut self, key: &str) -> Option<&mut KeyUsage> {
+        self.keys.iter_mut().find(|usage| usage.key == key)
+    }
+
+    fn total_budget(&self) -> u64 {
+        // Keys that don't work don't bring any quota with them.
+        let working: u64 = self.keys.iter().filter(|key| !key.invalid).count() as u64;
+        self.budget * working.max(1)
+    }
+
+    fn exhausted(&self, time: u64) -> bool {
+        // With more than one key, each one gets cut off on its own, so Google turning one
+        // down doesn't stop the others.
+        if self.keys.is_empty() {
+            return self.cut_off || self.used >= self.budget || time < self.slow_until;
+        }
+        !self.keys.iter().any(|key| key.usable(self.budget, time))
+    }
+
+    fn stretch(&self, delay: u64, time: DateTime<Utc>) -> u64 {
+        // By any point in the day we should have spent at most that fraction of the budget
+        // (plus a little burst room). If we're over, delays get stretched by how far over we are.
+        // Days with a DST change in them are an hour longer or shorter.
+        let today: NaiveDate = time.with_timezone(&Pacific).date_naive();
+        let (Some(start), Some(end)) = (
+            pacific_midnight(today),
+            pacific_midnight(today + Duration::days(1)),
+        ) else {
+            return delay;
+        };
+        let day_length: u64 = u64::try_from((end - start).num_seconds()).unwrap_or(86_400);
+        let elapsed: u64 = u64::try_from((time - start).num_seconds()).unwrap_or_default();
+
+        let budget: u64 = self.total_budget();
+        let allowed: u64 = budget * elapsed / day_length.max(1) + budget * BURST_PERCENT / 100;
+        if allowed == 0 || self.used <= allowed {
+            return delay;
+        }
+        delay.saturating_mul(self.used) / allowed
+    }
+}
+
+/// Sets how many units we want to stay under per day, per key. For every feed in the process.
+pub fn set_budget(budget: u64) {
+    ledger().budget = budget;
+}
+
+/// How many units a request costs, going off of the endpoint.
+#[must_use]
+pub fn cost_of(url: &str) -> u64 {
+    // https://developers.google.com/youtube/v3/determine_quota_cost
+    let endpoint: &str = endpoint_of(url);
+    match endpoint {
+        "search" => 100,
+        // channels, playlistItems, commentThreads, comments, videos...
+        _ => 1,
+    }
+}
+
+/// Which API endpoint a URL is for, like `commentThreads`.
+#[must_use]
+pub fn endpoint_of(url: &str) -> &str {
+    // "https://youtube.googleapis.com/youtube/v3/commentThreads?key=..." -> "commentThreads"
+    let path: &str = url.strip_prefix(API_URL).unwrap_or(url);
+    path.split('?').next().unwrap_or(path)
+}
+
+/// Counts a request against today's quota.
+///
+/// Google charges for a request even if it fails, so call this before sending it.
+pub fn record(url: &str) {
+    let cost: u64 = cost_of(url);
+    let mut ledger = ledger();
+    ledger.used += cost;
+    let entry = ledger
+        .calls
+        .entry(endpoint_of(url).to_string())
+        .or_default();
+    entry.0 += 1;
+    entry.1 += cost;
+    if let Some(usage) = key_of(url).and_then(|key| ledger.key(key)) {
+        usage.used += cost;
+    }
+    drop(ledger);
+}
+
+fn key_of(url: &str) -> Option<&str> {
+    // The `key` parameter of a URL.
+    let (_, query) = url.split_once('?')?;
+    query
+        .split('&')
+        .find_map(|param| param.strip_prefix("key="))
+}
+
+/// Starts tracking a key's quota separately. Adding the same key twice does nothing.
+pub fn add_key(key: &str) {
+    let mut ledger = ledger();
+    if ledger.key(key).is_none() {
+        ledger.keys.push(KeyUsage {
+            key: key.to_string(),
+            used: 0,
+            cut_off: false,
+            slow_until: 0,
+            invalid: false,
+        });
+    }
+}
+
+/// Google says we're out of quota (or going too fast), so stop until it resets.
+///
+/// Only counts with a single key. Keys that were [added](add_key) get marked one by one with
+/// [`mark_key_exhausted`] instead.
+pub fn mark_exhausted() {
+    ledger().cut_off = true;
+}
+
+/// Google says we're going too fast, so hold off for a minute.
+///
+/// Like [`mark_exhausted`], only counts with a single key.
+pub fn mark_rate_limited() {
+    ledger().slow_until = now() + RATE_LIMIT_COOLDOWN;
+}
+
+/// Google says this key is going too fast, so skip it for a minute.
+pub fn mark_key_rate_limited(key: &str) {
+    if let Some(usage) = ledger().key(key) {
+        usage.slow_until = now() + RATE_LIMIT_COOLDOWN;
+    }
+}
+
+/// Google says this key is out of quota, so skip it until it resets.
+pub fn mark_key_exhausted(key: &str) {
+    if let Some(usage) = ledger().key(key) {
+        usage.cut_off = true;
+    }
+}
+
+/// Google says this key isn't valid, so skip it for good.
+pub fn mark_key_invalid(key: &str) {
+    if let Some(usage) = ledger().key(key) {
+        usage.invalid = true;
+    }
+}
+
+/// Whether a key can be used right now, by our count and Google's. Keys that aren't being
+/// tracked separately always can.
+#[must_use]
+pub fn key_usable(key: &str) -> bool {
+    let mut ledger = ledger();
+    let budget: u64 = ledger.budget;
+    ledger
+        .key(key)
+        .is_none_or(|usage| usage.usable(budget, now()))
+}
+
+/// Whether Google said a key doesn't work at all. Keys that aren't being tracked separately
+/// never are.
+#[must_use]
+pub fn key_invalid(key: &str) -> bool {
+    ledger().key(key).is_some_and(|usage| usage.invalid)
+}
+
+/// Whether we're out of quota, by our count or Google's, either for today or until we've
+/// [slowed down](mark_rate_limited). With more than one key, that's once every key is out.
+#[must_use]
+pub fn exhausted() -> bool {
+    ledger().exhausted(now())
+}
+
+/// Whether we're only [out](exhausted) until we've slowed down, and not for the rest of the day.
+#[must_use]
+pub fn slowing_down() -> bool {
+    let ledger = ledger();
+    ledger.exhausted(now()) && !ledger.exhausted(u64::MAX)
+}
+
+/// Seconds until the quota resets at midnight Pacific time.
+#[must_use]
+pub fn seconds_until_reset() -> u64 {
+    seconds_until_reset_from(Utc::now())
+}
+
+fn seconds_until_reset_from(time: DateTime<Utc>) -> u64 {
+    // Time until the next midnight in California.
+    let tomorrow: NaiveDate = time.with_timezone(&Pacific).date_naive() + Duration::days(1);
+    pacific_midnight(tomorrow).map_or(0, |reset| {
+        u64::try_from((reset - time).num_seconds()).unwrap_or_default()
+    })
+}
+
+fn pacific_midnight(day: NaiveDate) -> Option<DateTime<Utc>> {
+    // Midnight always exists in Pacific time, DST changes happen at 2am.
+    let midnight = day.and_hms_opt(0, 0, 0)?;
+    Pacific
+        .from_local_datetime(&midnight)
+        .earliest()
+        .map(|midnight| midnight.with_timezone(&Utc))
+}
+
+/// Stretches a polling delay if we're spending faster than the budget allows.
+#[must_use]
+pub fn stretch(delay: u64) -> u64 {
+    ledger().stretch(delay, Utc::now())
+}
+
+/// A one line rundown of today's spending.
+#[must_use]
+pub fn summary() -> String {
+    let ledger = ledger();
+    let breakdown: Vec<String> = ledger
+        .calls
+        .iter()
+        .map(|(endpoint, (calls, units))| format!("{endpoint} {calls} calls/{units} units"))
+        .collect();
+    // Keys by number, never by the key itself.
+    let per_key: Vec<String> = ledger
+        .keys
+        .iter()
+        .enumerate()
+        .map(|(index, key)| {
+            let status: &str = match (key.invalid, key.cut_off) {
+                (true, _) => " invalid",
+                (false, true) => " out",
+                (false, false) => "",
+            };
+            format!("key {} {} units{status}", index + 1, key.used)
+        })
+        .collect();
+    let (used, budget) = (ledger.used, ledger.total_budget());
+    drop(ledger);
+    let keys: String = if per_key.is_empty() {
+        String::new()
+    } else {
+        format!(" [{}]", per_key.join(", "))
+    };
+    format!(
+        "Quota used today: {used}/{budget} units ({}){keys}",
+        breakdown.join(", ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::T;
+
+    fn pacific(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Pacific
+            .with_ymd_and_hms(year, month, day, hour, minute, 0)
+            .earliest()
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn fresh_ledger(time: DateTime<Utc>) -> QuotaLedger {
+        let mut ledger = QuotaLedger {
+            day: None,
+            used: 0,
+            calls: BTreeMap::new(),
+            budget: 1000,
+            cut_off: false,
+            slow_until: 0,
+            keys: Vec::new(),
+        };
+        ledger.roll_over(time);
+        ledger
+    }
+
+    fn usage(key: &str) -> KeyUsage {
+        KeyUsage {
+            key: key.to_string(),
+            used: 0,
+            cut_off: false,
+            slow_until: 0,
+            invalid: false,
+        }
+    }
+
+    #[test]
+    fn resets_happen_at_pacific_midnight() {
+        assert_eq!(seconds_until_reset_from(pacific(2024, 1, 15, 23, 59)), 60);
+        assert_eq!(seconds_until_reset_from(pacific(2024, 1, 15, 0, 0)), 86_400);
+        // Clocks go forward at 2am, so that day is an hour short.
+        assert_eq!(
+            seconds_until_reset_from(pacific(2024, 3, 10, 0, 0)),
+            23 * 3600
+        );
+        assert_eq!(
+            seconds_until_reset_from(pacific(2024, 3, 10, 12, 0)),
+            12 * 3600
+        );
+        // And back again, so that one's an hour long.
+        assert_eq!(
+            seconds_until_reset_from(pacific(2024, 11, 3, 0, 0)),
+            25 * 3600
+        );
+        assert_eq!(
+            seconds_until_reset_from(pacific(2024, 11, 3, 12, 0)),
+            12 * 3600
+        );
+    }
+
+    #[test]
+    fn a_new_day_clears_the_count() {
+        let mut ledger = fresh_ledger(pacific(2024, 1, 15, 9, 0));
+        ledger.used = 900;
+        ledger
+            .calls
+            .insert("commentThreads".to_string(), (900, 900));
+        ledger.cut_off = true;
+        ledger.keys.push(KeyUsage {
+            used: 900,
+            cut_off: true,
+            ..usage("a")
+        });
+        ledger.keys.push(KeyUsage {
+            invalid: true,
+            ..usage("b")
+        });
+
+        // Still the same day in California, even though it's the next one in UTC.
+        ledger.roll_over(pacific(2024, 1, 15, 23, 59));
+        assert_eq!(ledger.used, 900);
+        assert!(ledger.cut_off);
+
+        ledger.roll_over(pacific(2024, 1, 16, 0, 0));
+        assert_eq!(ledger.used, 0);
+        assert!(ledger.calls.is_empty());
+        assert!(!ledger.cut_off);
+        assert_eq!(ledger.keys[0].used, 0);
+        assert!(!ledger.keys[0].cut_off);
+        // A key that doesn't work won't start working tomorrow.
+        assert!(ledger.keys[1].invalid);
+    }
+
+    #[test]
+    fn one_key_running_out_leaves_the_others() {
+        let mut ledger = fresh_ledger(pacific(2024, 1, 15, 9, 0));
+        ledger.cut_off = true;
+        assert!(ledger.exhausted(T));
+
+        ledger.keys.extend([usage("a"), usage("b")]);
+        ledger.key("a").unwrap().cut_off = true;
+        assert!(!ledger.exhausted(T));
+        ledger.key("b").unwrap().used = 1000;
+        assert!(ledger.exhausted(T));
+    }
+
+    #[test]
+    fn slowing_down_only_takes_a_minute() {
+        let mut ledger = fresh_ledger(pacific(2024, 1, 15, 9, 0));
+        ledger.slow_until = T + RATE_LIMIT_COOLDOWN;
+        assert!(ledger.exhausted(T));
+        assert!(!ledger.exhausted(T + RATE_LIMIT_COOLDOWN));
+
+        // With more than one key, the others carry on meanwhile.
+        ledger.keys.extend([usage("a"), usage("b")]);
+        ledger.key("a").unwrap().slow_until = T + RATE_LIMIT_COOLDOWN;
+        assert!(!ledger.exhausted(T));
+        ledger.key("b").unwrap().slow_until = T + RATE_LIMIT_COOLDOWN;
+        assert!(ledger.exhausted(T));
+        assert!(!ledger.exhausted(T + RATE_LIMIT_COOLDOWN));
+    }
+
+    #[test]
+    fn delays_stretch_when_spending_too_fast() {
+        // Halfway through the day, 500 units plus 100 of burst room are fine.
+        let noon: DateTime<Utc> = pacific(2024, 1, 15, 12, 0);
+        let mut ledger = fresh_ledger(noon);
+        ledger.used = 600;
+        assert_eq!(ledger.stretch(60, noon), 60);
+        ledger.used = 1200;
+        assert_eq!(ledger.stretch(60, noon), 120);
+
+        // The burst room is there first thing in the morning.
+        let midnight: DateTime<Utc> = pacific(2024, 1, 15, 0, 0);
+        ledger.used = 100;
+        assert_eq!(ledger.stretch(60, midnight), 60);
+        ledger.used = 300;
+        assert_eq!(ledger.stretch(60, midnight), 180);
+
+        // More keys, more room.
+        ledger.keys.extend([usage("a"), usage("b")]);
+        ledger.used = 1200;
+        assert_eq!(ledger.stretch(60, noon), 60);
+    }
+
+    #[test]
+    fn pacing_follows_the_clocks_changing() {
+        // Noon on the short day is 11 hours in, out of 23.
+        let short_noon: DateTime<Utc> = pacific(2024, 3, 10, 12, 0);
+        let mut ledger = fresh_ledger(short_noon);
+        ledger.used = 578;
+        assert_eq!(ledger.stretch(60, short_noon), 60);
+        ledger.used = 1156;
+        assert_eq!(ledger.stretch(60, short_noon), 120);
+
+        // And on the long day it's 13 hours in, out of 25.
+        let long_noon: DateTime<Utc> = pacific(2024, 11, 3, 12, 0);
+        let mut ledger = fresh_ledger(long_noon);
+        ledger.used = 620;
+        assert_eq!(ledger.stretch(60, long_noon), 60);
+        ledger.used = 1240;
+        assert_eq!(ledger.stretch(60, long_noon), 120);
+
+        // The first hour of the long day still counts for something.
+        let early: DateTime<Utc> = pacific(2024, 11, 3, 0, 30);
+        ledger.used = 120;
+        assert_eq!(ledger.stretch(60, early), 60);
+    }
+}
@@ -0,0 +1,76 @@
+//! Keeping API keys out of anything that gets printed.
+//!
+//! Keys ride along in every request URL, and URLs have a way of ending up in error messages.
+//! Errors made by this crate go through [`redact`] before they're handed out, which blanks out
+//! anything passed to [`hide`], and the `key` parameter of any URL.
+//!
+//! Hidden secrets are process-wide, so a key hidden for one feed is blanked out of every
+//! feed's errors.
+
+use std::sync::{LazyLock, Mutex, PoisonError};
+
+use regex::Regex;
+
+// Every secret we've been told about. Errors get made all over, so this lives here.
+static SECRETS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+// `key=...` in a URL, whether or not we were told about that key.
+static KEY_PARAM: LazyLock<Option<Regex>> =
+    LazyLock::new(|| Regex::new(r"([?&]key=)[^&#\s]+").ok());
+
+/// What secrets get swapped out for.
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Makes [`redact`] blank out `secret` from now on. [`Feed::new`](crate::Feed::new) does this
+/// with its key.
+pub fn hide(secret: &str) {
+    if secret.is_empty() {
+        return;
+    }
+    let mut secrets = SECRETS.lock().unwrap_or_else(PoisonError::into_inner);
+    if !secrets.iter().any(|known| known == secret) {
+        secrets.push(secret.to_string());
+    }
+}
+
+/// `text`, with every hidden secret and every URL's `key` parameter blanked out.
+#[must_use]
+pub fn redact(text: &str) -> String {
+    let mut output: String = KEY_PARAM.as_ref().map_or_else(
+        || text.to_string(),
+        |regex| {
+            regex
+                .replace_all(text, format!("${{1}}{REDACTED}"))
+                .to_string()
+        },
+    );
+    for secret in SECRETS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .iter()
+    {
+        output = output.replace(secret.as_str(), REDACTED);
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_are_blanked_out() {
+        let url =
+            "https://youtube.googleapis.com/youtube/v3/commentThreads?key=AIzaUnknown&part=snippet";
+        assert_eq!(
+            redact(url),
+            "https://youtube.googleapis.com/youtube/v3/commentThreads?key=[REDACTED]&part=snippet"
+        );
+
+        hide("AIzaHiddenKey");
+        assert_eq!(
+            redact("the key AIzaHiddenKey didn't work"),
+            "the key [REDACTED] didn't work"
+        );
+    }
+}
@@ -0,0 +1,172 @@
+// The `search` subcommand, for digging through the comment archive.
+
+use std::path::PathBuf;
+
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use clap::Args;
+use colored::Colorize;
+use regex::Regex;
+
+use crate::{print_comment, print_reply};
+use youtube_comment_feed::archive::{Archive, ArchivedComment, SearchQuery};
+
+#[derive(Args, Debug)]
+pub struct SearchArgs {
+    // SQLite database made with --archive.
+    #[arg(long, required = true)]
+    archive: PathBuf,
+    // Only comments containing this text, ignoring case.
+    #[arg(long)]
+    text: Option<String>,
+    // Only comments matching this regex.
+    #[arg(long)]
+    regex: Option<String>,
+    // Only comments from authors with this in their name, ignoring case.
+    #[arg(long)]
+    author: Option<String>,
+    // Only comments on this video ID.
+    #[arg(long)]
+    video: Option<String>,
+    // Only comments from this day (YYYY-MM-DD) or time (RFC 3339) onwards.
+    #[arg(long, value_parser = parse_since)]
+    since: Option<u64>,
+    // Only comments up to the end of this day (YYYY-MM-DD) or before this time (RFC 3339).
+    #[arg(long, value_parser = parse_until)]
+    until: Option<u64>,
+    // Most comments to show. When there are more, the newest ones win.
+    #[arg(long, default_value_t = 100)]
+    limit: usize,
+}
+
+pub fn run(args: SearchArgs) {
+    // Check the regex before doing any work.
+    let regex: Option<Regex> = match args.regex.as_deref().map(Regex::new).transpose() {
+        Ok(okay) => okay,
+        Err(error) => {
+            eprintln!("{}", format!("Bad regex! : {error}").red());
+            std::process::exit(1)
+        }
+    };
+
+    // Opening would make a new empty archive, which is never what we want here.
+    if !args.archive.exists() {
+        eprintln!(
+            "{}",
+            format!("No archive at {}!", args.archive.display()).red()
+        );
+        std::process::exit(1)
+    }
+
+    let archive: Archive = match Archive::open(&args.archive) {
+        Ok(okay) => okay,
+        Err(error) => {
+            eprintln!("{}", error.to_string().red());
+            std::process::exit(1)
+        }
+    };
+
+    let query = SearchQuery {
+        text: args.text,
+        author: args.author,
+        video_id: args.video,
+        since: args.since,
+        until: args.until,
+    };
+
+    let mut results: Vec<ArchivedComment> = match archive.search(&query) {
+        Ok(okay) => okay,
+        Err(error) => {
+            eprintln!("{}", error.to_string().red());
+            std::process::exit(1)
+        }
+    };
+
+    // SQLite has no regex, so that filter happens here.
+    if let Some(regex) = regex {
+        results.retain(|result| regex.is_match(&result.comment.content));
+    }
+
+    // Keep the newest ones if there's too many.
+    let total: usize = results.len();
+    let results: &[ArchivedComment] = &results[total.saturating_sub(args.limit)..];
+
+    // Same look as the live feed, with a header whenever the video changes.
+    let mut last_video: Option<&str> = None;
+    for result in results {
+        if last_video != Some(result.video_id.as_str()) {
+            println!("{}", format!("{}:\n", result.video_title).cyan());
+            last_video = Some(&result.video_id);
+        }
+        println!("{}", format_timestamp(result.comment.timestamp).dimmed());
+        match &result.comment.reply_to {
+            None => print_comment(&result.comment),
+            Some(parent) => {
+                println!("{}", format!("In reply to {parent}:\n").dimmed());
+                print_reply(&result.comment);
+            }
+        }
+    }
+
+    println!(
+        "{}",
+        format!("Showing {} of {total} matching comments.", results.len()).green()
+    );
+}
+
+pub fn format_timestamp(timestamp: u64) -> String {
+    // Unix timestamp to something people can read.
+    let Ok(seconds) = i64::try_from(timestamp) else {
+        return timestamp.to_string();
+    };
+    Utc.timestamp_opt(seconds, 0).single().map_or_else(
+        || timestamp.to_string(),
+        |time| time.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+    )
+}
+
+fn parse_since(input: &str) -> Result<u64, String> {
+    parse_date(input, false)
+}
+
+fn parse_until(input: &str) -> Result<u64, String> {
+    parse_date(input, true)
+}
+
+fn parse_date(input: &str, end_of_day: bool) -> Result<u64, String> {
+    // Takes either a full RFC 3339 time, or just a day.
+    // Days start at midnight UTC, or end at the next midnight if `end_of_day` is set.
+    let timestamp: i64 = if let Ok(time) = DateTime::parse_from_rfc3339(input) {
+        time.timestamp()
+    } else {
+        let day: NaiveDate = NaiveDate::parse_from_str(input, "%Y-%m-%d")
+            .map_err(|_| format!("{input:?} is not a YYYY-MM-DD date or an RFC 3339 time"))?;
+        let day: NaiveDate = if end_of_day {
+            day.succ_opt().ok_or("date is too far in the future")?
+        } else {
+            day
+        };
+        let midnight = day.and_hms_opt(0, 0, 0).ok_or("bad date")?;
+        Utc.from_utc_datetime(&midnight).timestamp()
+    };
+    u64::try_from(timestamp).map_err(|_| "dates before 1970 aren't supported".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn days_cover_the_whole_day() {
+        // 2024-01-15 00:00:00 UTC.
+        let midnight: u64 = 1_705_276_800;
+        assert_eq!(parse_since("2024-01-15"), Ok(midnight));
+        assert_eq!(parse_until("2024-01-15"), Ok(midnight + 86_400));
+        // Exact times are taken as they are.
+        assert_eq!(parse_until("2024-01-15T00:00:00Z"), Ok(midnight));
+        assert_eq!(parse_since("2024-01-15T01:00:00+01:00"), Ok(midnight));
+        // Months roll over.
+        assert_eq!(parse_until("2024-02-29"), parse_since("2024-03-01"));
+        assert!(parse_since("15/01/2024").is_err());
+        assert!(parse_since("1969-12-31").is_err());
+    }
+}
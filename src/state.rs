@@ -0,0 +1,84 @@
+//! Saving and loading the tracked videos, so we can pick up where we left off after a restart.
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::TrackedVideo;
+
+/// Everything in a state file.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SavedState {
+    /// The timestamp of the poll cycle this was saved after.
+    pub saved_at: u64,
+    pub videos: Vec<TrackedVideo>,
+}
+
+/// Why a state file couldn't be loaded or saved.
+#[derive(Debug)]
+pub enum StateFail {
+    Io(std::io::Error),
+    BadJson(serde_json::Error),
+}
+
+impl std::fmt::Display for StateFail {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "Couldn't read or write the state file! : {error}"),
+            Self::BadJson(error) => write!(f, "State file is not valid! : {error}"),
+        }
+    }
+}
+
+/// Loads a state file, or `None` if there isn't one yet.
+///
+/// # Errors
+///
+/// The file couldn't be read, or isn't a state file.
+pub fn load(path: &Path) -> Result<Option<SavedState>, StateFail> {
+    // No file yet just means this is the first run.
+    let json: String = match std::fs::read_to_string(path) {
+        Ok(okay) => okay,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(error) => return Err(StateFail::Io(error)),
+    };
+
+    match serde_json::from_str(&json) {
+        Ok(okay) => Ok(Some(okay)),
+        Err(error) => Err(StateFail::BadJson(error)),
+    }
+}
+
+/// Saves the tracked videos to a state file, replacing whatever was there.
+///
+/// A crash mid-save leaves the old file alone, never a half written one.
+///
+/// # Errors
+///
+/// The file couldn't be written.
+pub fn save(path: &Path, videos: &[TrackedVideo], saved_at: u64) -> Result<(), StateFail> {
+    // Write to a temporary file next to the real one, then swap it in.
+    // A rename is atomic, so a crash mid-write can never leave a half written state file.
+    let json: String = match serde_json::to_string(&SavedStateRef { saved_at, videos }) {
+        Ok(okay) => okay,
+        Err(error) => return Err(StateFail::BadJson(error)),
+    };
+
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+
+    if let Err(error) = std::fs::write(&temp_path, json) {
+        return Err(StateFail::Io(error));
+    }
+    if let Err(error) = std::fs::rename(&temp_path, path) {
+        return Err(StateFail::Io(error));
+    }
+    Ok(())
+}
+
+// Same shape as `SavedState`, but borrows the videos so saving doesn't need a clone.
+#[derive(Serialize)]
+struct SavedStateRef<'a> {
+    saved_at: u64,
+    videos: &'a [TrackedVideo],
+}
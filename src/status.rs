@@ -0,0 +1,78 @@
+// The `status` subcommand, for seeing what a state file is keeping track of.
+
+use std::path::PathBuf;
+
+use clap::Args;
+use colored::Colorize;
+
+use crate::search::format_timestamp;
+use youtube_comment_feed::state::{self, SavedState};
+use youtube_comment_feed::TrackedVideo;
+
+#[derive(Args, Debug)]
+pub struct StatusArgs {
+    // State file made with --state.
+    #[arg(long, required = true)]
+    state: PathBuf,
+}
+
+pub fn run(args: &StatusArgs) {
+    let saved: SavedState = match state::load(&args.state) {
+        Ok(Some(okay)) => okay,
+        Ok(None) => {
+            eprintln!(
+                "{}",
+                format!("No state file at {}!", args.state.display()).red()
+            );
+            std::process::exit(1)
+        }
+        Err(error) => {
+            eprintln!("{}", error.to_string().red());
+            std::process::exit(1)
+        }
+    };
+
+    // Disabled videos go at the bottom, so they don't get lost in the middle of the list.
+    let (disabled, watching): (Vec<&TrackedVideo>, Vec<&TrackedVideo>) = saved
+        .videos
+        .iter()
+        .partition(|video| video.comments_disabled);
+
+    for video in &watching {
+        println!(
+            "{} {}",
+            video.title.cyan(),
+            format!(
+                "({}) every {}s, next check {}",
+                video.video_id,
+                video.recheck_delay,
+                format_timestamp(video.next_check)
+            )
+            .dimmed()
+        );
+    }
+    for video in &disabled {
+        println!(
+            "{} {} {}",
+            video.title.cyan(),
+            "comments disabled".yellow(),
+            format!(
+                "({}) next check {}",
+                video.video_id,
+                format_timestamp(video.next_check)
+            )
+            .dimmed()
+        );
+    }
+
+    println!(
+        "{}",
+        format!(
+            "Tracking {} videos, {} with comments disabled. Last saved {}.",
+            saved.videos.len(),
+            disabled.len(),
+            format_timestamp(saved.saved_at)
+        )
+        .green()
+    );
+}
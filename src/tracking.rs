@@ -0,0 +1,830 @@
+// Keeping track of which videos to check, when, and which of their comments are new.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{ApiReason, FeedError};
+use crate::fetch::{
+    comment_threads_url, get_comments_from_video, get_videos_by_id, get_videos_from_channel,
+    get_videos_from_playlist, Video, YTComment,
+};
+use crate::http::HttpClient;
+use crate::notice::{self, Notice};
+use crate::{now, quota};
+
+/// A video being watched for comments, and where we're at with it.
+///
+/// Serializable so it can be saved and picked back up later, see [`crate::state`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrackedVideo {
+    pub title: String,
+    pub video_id: String,
+    /// The channel it was uploaded to.
+    #[serde(default)] // Older state files don't have this, they only ever had one channel.
+    pub channel_id: String,
+    /// The timestamp of the most recent comment we saw last update.
+    pub most_recent_timestamp: u64,
+    /// New comments waiting to be handed out, oldest first. Not saved.
+    #[serde(skip)]
+    pub queued_comments: Vec<YTComment>,
+    /// How many seconds to wait until next update.
+    pub recheck_delay: u16,
+    /// The timestamp of when this video is due to be checked again.
+    pub next_check: u64,
+    /// How many replies each recent thread had when it was last seen.
+    pub reply_counts: HashMap<String, u64>,
+    /// IDs of the most recent comments we've already queued, oldest first.
+    pub seen_ids: VecDeque<String>,
+    /// The uploader turned comments off last time we checked.
+    #[serde(default)] // Older state files don't have this.
+    pub comments_disabled: bool,
+}
+
+// How many comment IDs to remember per video.
+// Only comments at or after the watermark get checked against these, so this can be small.
+pub const SEEN_ID_LIMIT: usize = 500;
+
+// How many threads' reply counts to remember per video.
+// Enough for every thread on the default 10 pages, with room to spare.
+pub const REPLY_COUNT_LIMIT: usize = 2000;
+
+/// How many seconds to wait between checks on videos with comments turned off, in case
+/// they get turned back on. Every check costs quota, so this is a lot slower than normal.
+pub const DISABLED_RECHECK_DELAY: u64 = 6 * 60 * 60;
+
+/// Bounds for how often videos get polled, in seconds.
+#[derive(Debug, Clone, Copy)]
+pub struct PollSchedule {
+    /// Fastest a video will be polled. Used for videos with fresh comments.
+    /// Should be no bigger than `max_delay`, and wins if it is.
+    pub min_delay: u16,
+    /// Slowest a video will be polled. Quiet videos back off up to this.
+    pub max_delay: u16,
+}
+
+impl Default for PollSchedule {
+    fn default() -> Self {
+        Self {
+            min_delay: 10,
+            max_delay: 3600,
+        }
+    }
+}
+
+impl PollSchedule {
+    /// How long to wait before the first check of a video that is `age` seconds old.
+    /// Brand new uploads start at the minimum, and the delay doubles for every day of age.
+    #[must_use]
+    pub fn initial_delay(self, age: u64) -> u16 {
+        let doublings: u64 = (age / 86_400).min(16);
+        let delay: u64 = u64::from(self.min_delay) << doublings;
+        let delay: u64 = delay
+            .min(u64::from(self.max_delay))
+            .max(u64::from(self.min_delay));
+        u16::try_from(delay).unwrap_or(self.max_delay)
+    }
+
+    /// How long to wait after a check, given the last delay and whether that check found anything.
+    /// New comments mean people are talking, so check again soon. Otherwise back off exponentially.
+    #[must_use]
+    pub fn next_delay(self, current: u16, got_new: bool) -> u16 {
+        if got_new {
+            return self.min_delay;
+        }
+        // Not clamp, that panics if the bounds are backwards.
+        current
+            .saturating_mul(2)
+            .min(self.max_delay)
+            .max(self.min_delay)
+    }
+}
+
+/// Adds any videos on the channel that aren't in `old` yet.
+///
+/// New videos only report comments newer than `start_timestamp`, and get polled faster the
+/// newer they are.
+///
+/// # Errors
+///
+/// Any failed request while listing the channel's videos.
+pub fn update_video_list(
+    client: &dyn HttpClient,
+    old: Vec<TrackedVideo>,
+    channel_id: &str,
+    key: &str,
+    start_timestamp: u64,
+    schedule: PollSchedule,
+) -> Result<Vec<TrackedVideo>, FeedError> {
+    // This function takes in the list of videos, checks the channel to see
+    // if there are videos on the channel that do not exist in the list yet.
+
+    // grab all of the videos off of the channel
+    // Nothing changed since last time, so no new videos either.
+    let Some(mut current_videos) = get_videos_from_channel(client, key, channel_id)? else {
+        return Ok(old);
+    };
+    // They're all from this channel, whatever the playlist says.
+    for video in &mut current_videos {
+        video.channel_id = channel_id.to_string();
+    }
+
+    // Video list is good, now lets compare.
+
+    if all_tracked(&old, &current_videos) {
+        // The same! exit early
+        return Ok(old);
+    }
+
+    Ok(merge_video_list(
+        old,
+        current_videos,
+        start_timestamp,
+        schedule,
+    ))
+}
+
+/// Adds any videos in the playlist that aren't in `old` yet. Same as [`update_video_list`],
+/// but for a playlist instead of a channel's uploads.
+///
+/// # Errors
+///
+/// Any failed request while listing the playlist.
+pub fn update_video_list_from_playlist(
+    client: &dyn HttpClient,
+    old: Vec<TrackedVideo>,
+    playlist_id: &str,
+    key: &str,
+    start_timestamp: u64,
+    schedule: PollSchedule,
+) -> Result<Vec<TrackedVideo>, FeedError> {
+    let current_videos: Vec<Video> = get_videos_from_playlist(client, key, playlist_id)?;
+    if all_tracked(&old, &current_videos) {
+        return Ok(old);
+    }
+    Ok(merge_video_list(
+        old,
+        current_videos,
+        start_timestamp,
+        schedule,
+    ))
+}
+
+/// Adds any of `video_ids` that aren't in `old` yet. Same as [`update_video_list`], but for
+/// a fixed set of videos. Once they're all in, this doesn't make any requests.
+///
+/// # Errors
+///
+/// Any failed request while looking the videos up.
+pub fn update_video_list_from_ids(
+    client: &dyn HttpClient,
+    old: Vec<TrackedVideo>,
+    video_ids: &[String],
+    key: &str,
+    start_timestamp: u64,
+    schedule: PollSchedule,
+) -> Result<Vec<TrackedVideo>, FeedError> {
+    let missing: Vec<String> = video_ids
+        .iter()
+        .filter(|id| !old.iter().any(|video| &video.video_id == *id))
+        .cloned()
+        .collect();
+    if missing.is_empty() {
+        return Ok(old);
+    }
+    let current_videos: Vec<Video> = get_videos_by_id(client, key, &missing)?;
+    Ok(merge_video_list(
+        old,
+        current_videos,
+        start_timestamp,
+        schedule,
+    ))
+}
+
+fn all_tracked(old: &[TrackedVideo], current_videos: &[Video]) -> bool {
+    // Whether there's nothing new to add. Going by IDs, not just the count, since a deleted
+    // video and a new upload between checks add up to the same length. With the list's ETag
+    // already stored, a missed upload wouldn't get another chance until the list changes again.
+    let tracked: HashSet<&str> = old.iter().map(|video| video.video_id.as_str()).collect();
+    current_videos
+        .iter()
+        .all(|video| tracked.contains(video.id.as_str()))
+}
+
+fn merge_video_list(
+    old: Vec<TrackedVideo>,
+    current_videos: Vec<Video>,
+    start_timestamp: u64,
+    schedule: PollSchedule,
+) -> Vec<TrackedVideo> {
+    // Start tracking anything in `current_videos` that isn't in `old` yet.
+
+    // Build the new videos into a TrackedVideo
+    let mut new_tracked_videos: Vec<TrackedVideo> = Vec::new();
+
+    let current_time: u64 = now();
+
+    for i in current_videos {
+        new_tracked_videos.push(TrackedVideo {
+            title: i.title,
+            video_id: i.id,
+            channel_id: i.channel_id,
+            most_recent_timestamp: start_timestamp,
+            queued_comments: [].to_vec(),
+            recheck_delay: schedule.initial_delay(current_time.saturating_sub(i.published)),
+            next_check: 0, // Check right away.
+            reply_counts: HashMap::new(),
+            seen_ids: VecDeque::new(),
+            comments_disabled: false,
+        });
+    }
+
+    // Combine the new list with the old list
+    // and remove dupes.
+
+    //`old` comes first to make sure we discard matching news, not olds.
+    let mut output: Vec<TrackedVideo> = old.into_iter().chain(new_tracked_videos).collect();
+    output.sort_by(|a, b| a.video_id.cmp(&b.video_id));
+    output.dedup_by(|a, b| a.video_id == b.video_id);
+
+    // we're done!
+    output
+}
+
+/// Where a feed gets its videos from.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum VideoSource {
+    /// Every upload on the feed's channel.
+    #[default]
+    Channel,
+    /// Everything in a playlist, including anything added to it later.
+    Playlist(String),
+    /// Just these videos.
+    Videos(Vec<String>),
+}
+
+/// How much work a poll is allowed to do.
+#[derive(Debug, Clone, Copy)]
+pub struct FetchLimits {
+    /// Most pages of 100 comments to grab from a video in one check.
+    pub max_pages: u16,
+    /// Most videos to check at the same time.
+    pub parallel: usize,
+}
+
+impl Default for FetchLimits {
+    fn default() -> Self {
+        Self {
+            max_pages: 10,
+            parallel: 4,
+        }
+    }
+}
+
+/// Checks every video that's due, adding new comments to its
+/// [`queued_comments`](TrackedVideo::queued_comments) and scheduling its next check.
+///
+/// Up to [`FetchLimits::parallel`] videos are checked at once. The videos come back in the
+/// same order they went in, whichever finished first. Videos that have been deleted are
+/// dropped, and ones that fail are tried again later.
+///
+/// # Errors
+///
+/// Quota or key trouble, which would fail for every other video too.
+pub fn queue_comments(
+    client: &dyn HttpClient,
+    video_list: &[TrackedVideo],
+    key: &str,
+    current_time: u64,
+    schedule: PollSchedule,
+    limits: FetchLimits,
+) -> Result<Vec<TrackedVideo>, FeedError> {
+    // This function takes in a list of tracked videos, and updates each entry with
+    // new comments on those videos. Videos that aren't due for a check yet are
+    // passed through untouched.
+
+    // Nothing due, no need to spin up any workers.
+    let due: usize = video_list
+        .iter()
+        .filter(|video| video.next_check <= current_time)
+        .count();
+    if due == 0 {
+        return Ok(video_list.to_vec());
+    }
+
+    // Each worker grabs the next unclaimed video until there aren't any left.
+    let next_index: AtomicUsize = AtomicUsize::new(0);
+    // Set once someone hits an error that'll fail every other video too.
+    let give_up: AtomicBool = AtomicBool::new(false);
+
+    let mut results: Vec<(usize, Result<Option<TrackedVideo>, FeedError>)> = Vec::new();
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..limits.parallel.clamp(1, due))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done: Vec<(usize, Result<Option<TrackedVideo>, FeedError>)> =
+                        Vec::new();
+                    while !give_up.load(Ordering::Relaxed) {
+                        let index: usize = next_index.fetch_add(1, Ordering::Relaxed);
+                        let Some(video) = video_list.get(index) else {
+                            break;
+                        };
+                        let result =
+                            check_video(client, video.clone(), key, current_time, schedule, limits);
+                        if matches!(&result, Err(error) if error.is_fatal()) {
+                            give_up.store(true, Ordering::Relaxed);
+                        }
+                        done.push((index, result));
+                    }
+                    done
+                })
+            })
+            .collect();
+
+        for worker in workers {
+            match worker.join() {
+                Ok(mut done) => results.append(&mut done),
+                Err(panic) => std::panic::resume_unwind(panic),
+            }
+        }
+    });
+
+    // Back into the order they came in.
+    results.sort_by_key(|(index, _)| *index);
+
+    let mut output_list: Vec<TrackedVideo> = Vec::new();
+    let mut failure: Option<FeedError> = None;
+    for (_, result) in results {
+        match result {
+            Ok(Some(video)) => output_list.push(video),
+            Ok(None) => (),
+            Err(error) => {
+                failure.get_or_insert(error);
+            }
+        }
+    }
+
+    // If anyone gave up, so does the whole sweep.
+    if let Some(error) = failure {
+        // Whatever did get checked is being thrown away, so it can't count as seen.
+        for video in video_list
+            .iter()
+            .filter(|video| video.next_check <= current_time)
+        {
+            client.forget(&comment_threads_url(key, &video.video_id, None));
+        }
+        return Err(error);
+    }
+
+    // All the comments should be updated now!
+    Ok(output_list)
+}
+
+fn check_video(
+    client: &dyn HttpClient,
+    mut video: TrackedVideo,
+    key: &str,
+    current_time: u64,
+    schedule: PollSchedule,
+    limits: FetchLimits,
+) -> Result<Option<TrackedVideo>, FeedError> {
+    // Checks one video for new comments. Gives back the updated video, or nothing if it
+    // should stop being tracked.
+
+    // Not time to check this one yet, skip it.
+    if video.next_check > current_time {
+        return Ok(Some(video));
+    }
+
+    // Grab every comment on this video since the last one we saw
+    let mut comments: Vec<YTComment> = match get_comments_from_video(
+        client,
+        key,
+        &video.video_id,
+        video.most_recent_timestamp,
+        limits.max_pages,
+        &video.reply_counts,
+    ) {
+        Ok((messages, reply_counts)) => {
+            if video.comments_disabled {
+                notice::send(&Notice::CommentsBack {
+                    title: video.title.clone(),
+                });
+                video.comments_disabled = false;
+            }
+            merge_reply_counts(&mut video.reply_counts, reply_counts);
+            messages
+        }
+        // Quota and key trouble will hit every other video too, so give up for now.
+        Err(error) if error.is_fatal() => return Err(error),
+        Err(error) if error.reason() == Some(&ApiReason::VideoNotFound) => {
+            // Deleted (or made private), nothing left to watch.
+            notice::send(&Notice::VideoGone {
+                title: video.title.clone(),
+            });
+            return Ok(None);
+        }
+        Err(error) if error.reason() == Some(&ApiReason::CommentsDisabled) => {
+            // Nothing to see here, but comments might get turned back on some day.
+            if !video.comments_disabled {
+                notice::send(&Notice::CommentsDisabled {
+                    title: video.title.clone(),
+                    recheck_hours: DISABLED_RECHECK_DELAY / 3600,
+                });
+                video.comments_disabled = true;
+            }
+            video.next_check = current_time + quota::stretch(DISABLED_RECHECK_DELAY);
+            return Ok(Some(video));
+        }
+        Err(error) => {
+            // Just this video, try it again later.
+            notice::send(&Notice::VideoFailed {
+                title: video.title.clone(),
+                error: error.to_string(),
+            });
+            video.recheck_delay = schedule.next_delay(video.recheck_delay, false);
+            video.next_check = current_time + quota::stretch(u64::from(video.recheck_delay));
+            return Ok(Some(video));
+        }
+    };
+
+    // Now check if the comments are newer than most_recent_timestamp
+    // Comments posted in the same second as the watermark might be old or new,
+    // so the IDs we've already seen settle it.
+    let mut new_comments: Vec<YTComment> = Vec::new();
+    let mut out_updated: TrackedVideo = video.clone();
+    let mut new_max_timestamp: u64 = video.most_recent_timestamp;
+
+    // Oldest first, so `seen_ids` stays in order.
+    comments.sort_by_key(|comment| comment.timestamp);
+
+    for comment in comments {
+        if comment.timestamp >= video.most_recent_timestamp
+            && !out_updated.seen_ids.contains(&comment.id)
+        {
+            // comment is new! add to the list!
+            // update the new timestamp if its the new best
+            if comment.timestamp >= new_max_timestamp {
+                new_max_timestamp = comment.timestamp;
+            }
+
+            // remember it, and forget the oldest one if we're full.
+            out_updated.seen_ids.push_back(comment.id.clone());
+            if out_updated.seen_ids.len() > SEEN_ID_LIMIT {
+                out_updated.seen_ids.pop_front();
+            }
+
+            // add it to the comment buffer!
+            new_comments.push(comment);
+        }
+    }
+
+    // Speed up or slow down depending on if anything new showed up.
+    out_updated.recheck_delay = schedule.next_delay(video.recheck_delay, !new_comments.is_empty());
+    // Stretched if we're spending quota too fast.
+    out_updated.next_check = current_time + quota::stretch(u64::from(out_updated.recheck_delay));
+
+    // Add the comments (if there are any) to the TrackedVideo
+    out_updated.queued_comments.append(&mut new_comments);
+
+    // Apply the new most recent timestamp
+    out_updated.most_recent_timestamp = new_max_timestamp;
+
+    Ok(Some(out_updated))
+}
+
+fn merge_reply_counts(known: &mut HashMap<String, u64>, latest: HashMap<String, u64>) {
+    // Threads that weren't on the pages this time keep their old counts, in case a later
+    // check reaches back that far. Once we're full, they're the ones that get forgotten.
+    let mut merged: HashMap<String, u64> = latest;
+    for (thread_id, count) in known.drain() {
+        if merged.len() >= REPLY_COUNT_LIMIT {
+            break;
+        }
+        merged.entry(thread_id).or_insert(count);
+    }
+    *known = merged;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{api_error, heard, listen, rfc3339, thread, FixtureClient, T};
+    use serde_json::json;
+
+    fn schedule() -> PollSchedule {
+        PollSchedule {
+            min_delay: 10,
+            max_delay: 3600,
+        }
+    }
+
+    fn limits() -> FetchLimits {
+        FetchLimits {
+            max_pages: 10,
+            parallel: 1,
+        }
+    }
+
+    fn tracked(video_id: &str) -> TrackedVideo {
+        TrackedVideo {
+            title: format!("Video {video_id}"),
+            video_id: video_id.to_string(),
+            channel_id: "UC123".to_string(),
+            most_recent_timestamp: T,
+            queued_comments: Vec::new(),
+            recheck_delay: 10,
+            next_check: 0,
+            reply_counts: HashMap::new(),
+            seen_ids: VecDeque::new(),
+            comments_disabled: false,
+        }
+    }
+
+    #[test]
+    fn queueing_only_keeps_new_comments() {
+        let client = FixtureClient::new().with(
+            "commentThreads?",
+            &json!({
+                "items": [
+                    thread("new", T + 10, 0, &[]),
+                    thread("same_second", T, 0, &[]),
+                    thread("seen", T, 0, &[]),
+                    thread("old", T - 10, 0, &[]),
+                ]
+            }),
+        );
+        let mut video = tracked("vid");
+        video.seen_ids.push_back("seen".to_string());
+
+        let videos =
+            queue_comments(&client, &[video], "KEY", T + 20, schedule(), limits()).unwrap();
+
+        let ids: Vec<&str> = videos[0]
+            .queued_comments
+            .iter()
+            .map(|comment| comment.id.as_str())
+            .collect();
+        assert_eq!(ids, ["same_second", "new"]);
+        assert_eq!(videos[0].most_recent_timestamp, T + 10);
+        assert_eq!(videos[0].recheck_delay, 10);
+    }
+
+    #[test]
+    fn reply_counts_outlast_the_pages_they_were_on() {
+        let client = FixtureClient::new().with(
+            "commentThreads?",
+            &json!({ "items": [thread("t1", T + 10, 0, &[])] }),
+        );
+        let mut video = tracked("vid");
+        video.reply_counts.insert("t1".to_string(), 3);
+        video.reply_counts.insert("offscreen".to_string(), 4);
+
+        let videos =
+            queue_comments(&client, &[video], "KEY", T + 20, schedule(), limits()).unwrap();
+        assert_eq!(videos[0].reply_counts.get("t1"), Some(&0));
+        assert_eq!(videos[0].reply_counts.get("offscreen"), Some(&4));
+
+        // But not forever.
+        let mut known: HashMap<String, u64> = (0..REPLY_COUNT_LIMIT)
+            .map(|number| (format!("old{number}"), 1))
+            .collect();
+        merge_reply_counts(&mut known, HashMap::from([("new".to_string(), 2)]));
+        assert_eq!(known.len(), REPLY_COUNT_LIMIT);
+        assert_eq!(known.get("new"), Some(&2));
+    }
+
+    #[test]
+    fn queueing_skips_videos_that_arent_due() {
+        let client = FixtureClient::new();
+        let mut video = tracked("vid");
+        video.next_check = T + 100;
+        let videos = queue_comments(&client, &[video], "KEY", T, schedule(), limits()).unwrap();
+        assert_eq!(videos.len(), 1);
+        assert!(client.requests().is_empty());
+    }
+
+    #[test]
+    fn queueing_remembers_disabled_comments() {
+        let client =
+            FixtureClient::new().with("commentThreads?", &api_error(403, "commentsDisabled"));
+        let videos =
+            queue_comments(&client, &[tracked("vid")], "KEY", T, schedule(), limits()).unwrap();
+        assert!(videos[0].comments_disabled);
+        assert!(videos[0].next_check >= T + DISABLED_RECHECK_DELAY);
+
+        // And forgets once they're back on.
+        let client = FixtureClient::new().with("commentThreads?", &json!({ "items": [] }));
+        let videos = queue_comments(
+            &client,
+            &videos,
+            "KEY",
+            T + DISABLED_RECHECK_DELAY * 2,
+            schedule(),
+            limits(),
+        )
+        .unwrap();
+        assert!(!videos[0].comments_disabled);
+    }
+
+    #[test]
+    fn queueing_drops_deleted_videos_and_backs_off_broken_ones() {
+        listen();
+        let client = FixtureClient::new()
+            .with("videoId=gone", &api_error(404, "videoNotFound"))
+            .failing("videoId=flaky");
+        let videos = queue_comments(
+            &client,
+            &[tracked("gone"), tracked("flaky")],
+            "KEY",
+            T,
+            schedule(),
+            limits(),
+        )
+        .unwrap();
+        assert_eq!(videos.len(), 1);
+        assert_eq!(videos[0].video_id, "flaky");
+        assert_eq!(videos[0].recheck_delay, 20);
+        let seen: Vec<Notice> = heard();
+        assert!(seen.contains(&Notice::VideoGone {
+            title: "Video gone".to_string()
+        }));
+        assert!(seen.iter().any(
+            |notice| matches!(notice, Notice::VideoFailed { title, .. } if title == "Video flaky")
+        ));
+    }
+
+    #[test]
+    fn queueing_gives_up_on_bad_keys() {
+        let body = json!({
+            "error": {
+                "code": 400,
+                "message": "API key not valid.",
+                "errors": [{ "reason": "keyInvalid" }]
+            }
+        });
+        let client = FixtureClient::new().with("commentThreads?", &body);
+        let error = queue_comments(
+            &client,
+            &[tracked("a"), tracked("b")],
+            "KEY",
+            T,
+            schedule(),
+            limits(),
+        )
+        .unwrap_err();
+        assert_eq!(error.reason(), Some(&ApiReason::KeyInvalid));
+        assert_eq!(client.requests().len(), 1);
+        // Nothing from this sweep got kept, so nothing from it counts as seen either.
+        assert!(client
+            .forgotten()
+            .contains(&comment_threads_url("KEY", "b", None)));
+    }
+
+    #[test]
+    fn unchanged_channels_keep_their_videos() {
+        let client = FixtureClient::new()
+            .with(
+                "channels?part=contentDetails",
+                &json!({
+                    "items": [{ "contentDetails": { "relatedPlaylists": { "uploads": "UU123" } } }]
+                }),
+            )
+            .not_modified("playlistItems?");
+        let videos =
+            update_video_list(&client, vec![tracked("a")], "UC123", "KEY", T, schedule()).unwrap();
+        assert_eq!(videos.len(), 1);
+        assert_eq!(videos[0].video_id, "a");
+    }
+
+    #[test]
+    fn swapped_videos_still_count_as_new() {
+        // `a` got deleted and `c` uploaded, so the list is the same length as before.
+        let item = |id: &str| {
+            json!({
+                "snippet": { "title": format!("Video {id}") },
+                "contentDetails": { "videoId": id, "videoPublishedAt": rfc3339(T) }
+            })
+        };
+        let client = FixtureClient::new()
+            .with(
+                "channels?part=contentDetails",
+                &json!({
+                    "items": [{ "contentDetails": { "relatedPlaylists": { "uploads": "UU123" } } }]
+                }),
+            )
+            .with(
+                "playlistItems?",
+                &json!({ "items": [item("b"), item("c")] }),
+            );
+        let old = vec![tracked("a"), tracked("b")];
+        let videos = update_video_list(&client, old, "UC123", "KEY", T, schedule()).unwrap();
+        let ids: Vec<&str> = videos.iter().map(|video| video.video_id.as_str()).collect();
+        assert_eq!(ids, ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn parallel_queueing_keeps_the_order() {
+        let client = FixtureClient::new()
+            .with("videoId=v13", &api_error(404, "videoNotFound"))
+            .with(
+                "commentThreads?",
+                &json!({ "items": [thread("t2", T + 20, 0, &[]), thread("t1", T + 10, 0, &[])] }),
+            );
+        let videos: Vec<TrackedVideo> = (0..50).map(|i| tracked(&format!("v{i}"))).collect();
+        let parallel = FetchLimits {
+            max_pages: 10,
+            parallel: 8,
+        };
+
+        let videos = queue_comments(&client, &videos, "KEY", T + 30, schedule(), parallel).unwrap();
+
+        let ids: Vec<String> = videos.iter().map(|video| video.video_id.clone()).collect();
+        let expected: Vec<String> = (0..50)
+            .filter(|&i| i != 13)
+            .map(|i| format!("v{i}"))
+            .collect();
+        assert_eq!(ids, expected);
+        for video in &videos {
+            let comment_ids: Vec<&str> = video
+                .queued_comments
+                .iter()
+                .map(|comment| comment.id.as_str())
+                .collect();
+            assert_eq!(comment_ids, ["t1", "t2"]);
+        }
+        assert_eq!(client.requests().len(), 50);
+    }
+
+    #[test]
+    fn new_uploads_are_tracked_with_their_channel() {
+        let client = FixtureClient::new()
+            .with(
+                "channels?part=contentDetails",
+                &json!({
+                    "items": [{ "contentDetails": { "relatedPlaylists": { "uploads": "UU456" } } }]
+                }),
+            )
+            .with(
+                "playlistItems?",
+                &json!({
+                    "items": [
+                        {
+                            "snippet": { "title": "Video b" },
+                            "contentDetails": { "videoId": "b", "videoPublishedAt": rfc3339(T) }
+                        },
+                        {
+                            "snippet": { "title": "Video a" },
+                            "contentDetails": { "videoId": "a", "videoPublishedAt": rfc3339(T) }
+                        }
+                    ]
+                }),
+            );
+        let videos =
+            update_video_list(&client, vec![tracked("a")], "UC456", "KEY", T, schedule()).unwrap();
+        let channels: Vec<(&str, &str)> = videos
+            .iter()
+            .map(|video| (video.video_id.as_str(), video.channel_id.as_str()))
+            .collect();
+        assert_eq!(channels, [("a", "UC123"), ("b", "UC456")]);
+    }
+
+    #[test]
+    fn picked_videos_are_only_looked_up_once() {
+        let client = FixtureClient::new().with(
+            "videos?",
+            &json!({
+                "items": [{
+                    "id": "b",
+                    "snippet": { "title": "Video b", "channelId": "UC456", "publishedAt": rfc3339(T) }
+                }]
+            }),
+        );
+        let ids = ["a".to_string(), "b".to_string()];
+        let videos =
+            update_video_list_from_ids(&client, vec![tracked("a")], &ids, "KEY", T, schedule())
+                .unwrap();
+        let channels: Vec<(&str, &str)> = videos
+            .iter()
+            .map(|video| (video.video_id.as_str(), video.channel_id.as_str()))
+            .collect();
+        assert_eq!(channels, [("a", "UC123"), ("b", "UC456")]);
+        assert!(client.requests()[0].contains("&id=b"));
+
+        // Nothing left to look up, so nothing gets asked.
+        update_video_list_from_ids(&client, videos, &ids, "KEY", T, schedule()).unwrap();
+        assert_eq!(client.requests().len(), 1);
+    }
+
+    #[test]
+    fn backwards_schedules_stick_to_the_minimum() {
+        let schedule = PollSchedule {
+            min_delay: 60,
+            max_delay: 30,
+        };
+        assert_eq!(schedule.next_delay(45, false), 60);
+        assert_eq!(schedule.next_delay(45, true), 60);
+        assert_eq!(schedule.initial_delay(10 * 86_400), 60);
+    }
+}